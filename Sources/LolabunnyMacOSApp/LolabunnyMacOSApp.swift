@@ -1,3 +1,4 @@
+import AppKit
 import Darwin
 import Foundation
 import LolabunnyMacOSAppCore
@@ -10,6 +11,13 @@ struct LolabunnyMacOSApp: App {
 
     init() {
         EmbeddedServer.shared.start()
+        NotificationCenter.default.addObserver(
+            forName: NSApplication.willTerminateNotification,
+            object: nil,
+            queue: nil
+        ) { _ in
+            EmbeddedServer.shared.stop()
+        }
 
         let model = AppDelegate()
         _app = StateObject(wrappedValue: model)
@@ -26,6 +34,8 @@ private final class EmbeddedServer: @unchecked Sendable {
 
     private let queue = DispatchQueue(label: "lolabunny.embedded-lolabunny-server", qos: .userInitiated)
     private var started = false
+    private let serverLock = NSLock()
+    private var server: HTTPServer?
 
     private init() {}
 
@@ -64,9 +74,12 @@ private final class EmbeddedServer: @unchecked Sendable {
                 let server = HTTPServer(
                     address: address,
                     port: port,
-                    router: CommandRouter(),
+                    router: CommandRouter(config: config),
                     config: config
                 )
+                self.serverLock.lock()
+                self.server = server
+                self.serverLock.unlock()
                 try server.run()
             } catch {
                 log("embedded lolabunny-server failed: \(error.localizedDescription)")
@@ -74,6 +87,14 @@ private final class EmbeddedServer: @unchecked Sendable {
         }
     }
 
+    /// Drains the embedded server, which also writes out history entries still queued in the background.
+    func stop() {
+        serverLock.lock()
+        let server = server
+        serverLock.unlock()
+        server?.stop()
+    }
+
     private func probeExistingServer(address: String, port: UInt16) -> String? {
         guard let url = URL(string: "http://\(address):\(port)/health") else {
             return nil
@@ -119,7 +119,33 @@ extension AppDelegate {
     }
 
     func openBindings() {
-        NSWorkspace.shared.open(Config.serverBaseURL)
+        openURL(Config.serverBaseURL)
+    }
+
+    func openURL(_ url: URL) {
+        let opened = Self.openURL(url, notifyOnError: Config.notifyOnError, using: NSWorkspace.shared.open) { [self] title, body in
+            postNotification(title: title, body: body)
+        }
+        if !opened {
+            log("failed to open \(url.absoluteString) in the default browser")
+        }
+    }
+
+    /// Opens `url` with `open`. When that fails and `notifyOnError` is set, `notify` gets a title and the
+    /// URL as the body, so it can still be copied.
+    static func openURL(
+        _ url: URL,
+        notifyOnError: Bool,
+        using open: (URL) -> Bool,
+        notify: (String, String) -> Void
+    ) -> Bool {
+        guard !open(url) else {
+            return true
+        }
+        if notifyOnError {
+            notify("Could not open browser", url.absoluteString)
+        }
+        return false
     }
 
     func quit() {
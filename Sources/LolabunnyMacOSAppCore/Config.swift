@@ -21,6 +21,8 @@ enum Config {
     /// Same host as `serve --address` (see `LolabunnyServerAddress`). Avoid `localhost` so health checks do not hit IPv6 while the lolabunny-server listens on IPv4.
     static var serverAddress: String { Config.plistString("LolabunnyServerAddress") ?? "127.0.0.1" }
     static var serverBaseURL: URL { URL(string: "http://\(serverAddress):\(serverPort)")! }
+    /// Post a notification with the URL when the browser cannot be opened. Off unless enabled.
+    static var notifyOnError: Bool { Config.plistBool("LolabunnyNotifyOnError") ?? false }
 
     static func plistString(_ key: String) -> String? {
         plistString(keys: [key])
@@ -66,6 +68,7 @@ enum Config {
         "LolabunnyServerVersion": "LOLABUNNY_SERVER_VERSION",
         "LolabunnyServerWatchdogIntervalSeconds": "LOLABUNNY_SERVER_WATCHDOG_INTERVAL_SECONDS",
         "LolabunnyDataRoot": "LOLABUNNY_DATA_ROOT",
+        "LolabunnyNotifyOnError": "LOLABUNNY_NOTIFY_ON_ERROR",
         "LolabunnyDefaultSearch": "LOLABUNNY_DEFAULT_SEARCH",
         "LolabunnyHistoryEnabled": "LOLABUNNY_HISTORY_ENABLED",
         "LolabunnyHistoryMaxEntries": "LOLABUNNY_HISTORY_MAX_ENTRIES",
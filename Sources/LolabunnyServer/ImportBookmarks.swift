@@ -0,0 +1,134 @@
+import Foundation
+import LolabunnyServerCore
+
+/// A browser bookmark with a keyword and a `%s` placeholder for the search terms.
+struct KeywordBookmark {
+    let keyword: String
+    let url: String
+    let title: String
+}
+
+/// Keyword bookmarks in a Netscape bookmarks HTML export, and how many bookmarks were skipped
+/// because they have no keyword or no `%s` in their URL.
+func parseKeywordBookmarks(html: String) -> (bookmarks: [KeywordBookmark], skipped: Int) {
+    guard let anchor = try? NSRegularExpression(
+        pattern: #"<a\s([^>]*)>(.*?)</a>"#,
+        options: [.caseInsensitive, .dotMatchesLineSeparators]
+    ) else {
+        return ([], 0)
+    }
+
+    let ns = html as NSString
+    var bookmarks: [KeywordBookmark] = []
+    var skipped = 0
+    for match in anchor.matches(in: html, range: NSRange(location: 0, length: ns.length)) {
+        let attributes = ns.substring(with: match.range(at: 1))
+        guard let url = bookmarkAttribute("href", in: attributes),
+              let keyword = bookmarkAttribute("shortcuturl", in: attributes)?.lowercased(),
+              url.contains("%s"),
+              !keyword.isEmpty,
+              keyword.allSatisfy({ $0.isASCII && ($0.isLetter || $0.isNumber || $0 == "-" || $0 == "_") }) else {
+            skipped += 1
+            continue
+        }
+        let title = decodeHTMLEntities(ns.substring(with: match.range(at: 2)))
+            .trimmingCharacters(in: .whitespacesAndNewlines)
+        bookmarks.append(KeywordBookmark(keyword: keyword, url: url, title: title))
+    }
+    return (bookmarks, skipped)
+}
+
+/// Lua command source that substitutes the URL-encoded arguments for every `%s` in the bookmark URL.
+func bookmarkCommandSource(for bookmark: KeywordBookmark) -> String {
+    let urlExpression = bookmark.url
+        .components(separatedBy: "%s")
+        .map(luaStringLiteral)
+        .joined(separator: " .. url_encode(args) .. ")
+    let description = (bookmark.title.isEmpty ? bookmark.url : bookmark.title)
+        .replacingOccurrences(of: "\"", with: "'")
+    return """
+    -- Imported from a browser keyword bookmark.
+    function process(full_args)
+      local args = get_args(full_args, \(luaStringLiteral(bookmark.keyword)))
+      return \(urlExpression)
+    end
+
+    return {
+      bindings = { \(luaStringLiteral(bookmark.keyword)) },
+      description = \(luaStringLiteral(description)),
+    }
+
+    """
+}
+
+func runImportBookmarks(_ arguments: [String]) throws {
+    guard let path = arguments.first else {
+        throw ServerError.message("usage: lolabunny import-bookmarks FILE")
+    }
+    let html = try String(contentsOf: URL(fileURLWithPath: (path as NSString).expandingTildeInPath), encoding: .utf8)
+    let (bookmarks, skipped) = parseKeywordBookmarks(html: html)
+
+    let commandsDirectory = Paths.userCommandsDirectory
+    try FileManager.default.createDirectory(at: commandsDirectory, withIntermediateDirectories: true)
+    var created = 0
+    for bookmark in bookmarks {
+        let file = commandsDirectory.appendingPathComponent("\(bookmark.keyword).lua")
+        if FileManager.default.fileExists(atPath: file.path) {
+            print("Skipped: \(file.path) already exists.")
+            continue
+        }
+        try bookmarkCommandSource(for: bookmark).write(to: file, atomically: true, encoding: .utf8)
+        print("Created \(file.path)")
+        created += 1
+    }
+    print("Created \(created) command(s); skipped \(skipped) bookmark(s) without a keyword and %s.")
+}
+
+/// Netscape bookmarks HTML with a keyword bookmark for every binding, to use commands in a browser
+/// without the server. The `{}` argument in a command's URL template becomes the browser's `%s`.
+/// Commands that produce no single URL for a placeholder argument are left out.
+func keywordBookmarksHTML(_ templates: [(command: CommandInfo, template: String?)]) -> String {
+    let entries = templates.flatMap { command, template -> [String] in
+        guard let template, let url = URL(string: template), url.scheme != nil else {
+            return []
+        }
+        let href = htmlEscape(template.replacingOccurrences(of: "{}", with: "%s"))
+        let title = htmlEscape(command.description.isEmpty ? command.bindings.first ?? "" : command.description)
+        return command.bindings.map { binding in
+            "    <DT><A HREF=\"\(href)\" SHORTCUTURL=\"\(htmlEscape(binding.lowercased()))\">\(title)</A>"
+        }
+    }
+    return """
+    <!DOCTYPE NETSCAPE-Bookmark-file-1>
+    <META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+    <TITLE>Lolabunny</TITLE>
+    <H1>Lolabunny</H1>
+    <DL><p>
+    \(entries.joined(separator: "\n"))
+    </DL><p>
+
+    """
+}
+
+private func bookmarkAttribute(_ name: String, in attributes: String) -> String? {
+    guard let regex = try? NSRegularExpression(
+        pattern: #"(?:^|\s)\#(name)\s*=\s*"([^"]*)""#,
+        options: [.caseInsensitive]
+    ) else {
+        return nil
+    }
+    let ns = attributes as NSString
+    guard let match = regex.firstMatch(in: attributes, range: NSRange(location: 0, length: ns.length)) else {
+        return nil
+    }
+    return decodeHTMLEntities(ns.substring(with: match.range(at: 1)))
+}
+
+private func decodeHTMLEntities(_ value: String) -> String {
+    value
+        .replacingOccurrences(of: "&quot;", with: "\"")
+        .replacingOccurrences(of: "&#39;", with: "'")
+        .replacingOccurrences(of: "&lt;", with: "<")
+        .replacingOccurrences(of: "&gt;", with: ">")
+        .replacingOccurrences(of: "&amp;", with: "&")
+}
@@ -0,0 +1,66 @@
+import CoreGraphics
+import CoreImage
+import Foundation
+
+/// Renders `text` as a QR code for the terminal, two modules per line using half blocks.
+/// Light modules are drawn so the code scans on dark terminal backgrounds.
+func qrCodeLines(for text: String) -> [String]? {
+    guard let filter = CIFilter(name: "CIQRCodeGenerator") else {
+        return nil
+    }
+    filter.setValue(Data(text.utf8), forKey: "inputMessage")
+    filter.setValue("M", forKey: "inputCorrectionLevel")
+    guard let image = filter.outputImage,
+          let cgImage = CIContext().createCGImage(image, from: image.extent) else {
+        return nil
+    }
+
+    let width = cgImage.width
+    let height = cgImage.height
+    var pixels = [UInt8](repeating: 255, count: width * height)
+    let drawn = pixels.withUnsafeMutableBytes { buffer -> Bool in
+        guard let context = CGContext(
+            data: buffer.baseAddress,
+            width: width,
+            height: height,
+            bitsPerComponent: 8,
+            bytesPerRow: width,
+            space: CGColorSpaceCreateDeviceGray(),
+            bitmapInfo: CGImageAlphaInfo.none.rawValue
+        ) else {
+            return false
+        }
+        context.draw(cgImage, in: CGRect(x: 0, y: 0, width: width, height: height))
+        return true
+    }
+    guard drawn else {
+        return nil
+    }
+
+    let quietZone = 2
+    func isLight(_ x: Int, _ y: Int) -> Bool {
+        guard (0..<width).contains(x), (0..<height).contains(y) else {
+            return true
+        }
+        return pixels[y * width + x] >= 128
+    }
+
+    var lines: [String] = []
+    for y in stride(from: -quietZone, to: height + quietZone, by: 2) {
+        var line = ""
+        for x in -quietZone..<(width + quietZone) {
+            switch (isLight(x, y), isLight(x, y + 1)) {
+            case (true, true):
+                line += "█"
+            case (true, false):
+                line += "▀"
+            case (false, true):
+                line += "▄"
+            case (false, false):
+                line += " "
+            }
+        }
+        lines.append(line)
+    }
+    return lines
+}
@@ -34,21 +34,38 @@ func run(arguments: [String]) throws -> Int32 {
     }
 
     if arguments.first == "serve" {
-        var config = AppConfig()
-        try applyServeOptions(Array(arguments.dropFirst()), to: &config)
+        var serveArguments = Array(arguments.dropFirst())
+        var config = try environmentConfig()
+        if let daemon = serveArguments.firstIndex(of: "--daemon") {
+            serveArguments.remove(at: daemon)
+            try applyServeOptions(serveArguments, to: &config)
+            return try startDaemon(serveArguments: serveArguments)
+        }
+        try applyServeOptions(serveArguments, to: &config)
         try runServer(config: config)
+        return 0
     }
 
-    var config = AppConfig()
+    var config = try environmentConfig()
     let parsed = try parseGlobalOptions(arguments, config: &config)
 
     if parsed.list {
-        printCommands(CommandRouter().allCommands())
+        printCommands(CommandRouter(config: config).allCommands())
+        return 0
+    }
+
+    let interactive = !parsed.noInteractive && isatty(STDIN_FILENO) == 1 && isatty(STDOUT_FILENO) == 1
+    if parsed.firstRun {
+        try runOnboarding(interactive: interactive, config: config)
         return 0
     }
 
     guard let command = parsed.positionals.first else {
-        printHelp()
+        if interactive, CommandRouter(config: config).allCommands().isEmpty {
+            try runOnboarding(interactive: true, config: config)
+        } else {
+            printHelp()
+        }
         return 0
     }
 
@@ -56,38 +73,280 @@ func run(arguments: [String]) throws -> Int32 {
     case "help", "--help", "-h":
         printHelp()
     case "bindings", "list":
-        printCommands(CommandRouter().allCommands())
+        let router = CommandRouter(config: config)
+        if parsed.positionals.dropFirst().contains("--templates") {
+            printCommandTemplates(router.urlTemplates())
+        } else if parsed.positionals.dropFirst().contains("--bookmarks") {
+            print(keywordBookmarksHTML(router.urlTemplates()), terminator: "")
+        } else {
+            printCommands(router.allCommands())
+        }
     case "completion":
         print("# Shell completion generation is not required for the Swift lolabunny-server.")
     case "pid-file":
         print(Paths.pidFile.path)
+    case "doctor":
+        return runDoctor(config: config)
+    case "history":
+        try runHistory(Array(parsed.positionals.dropFirst()), config: config, dryRun: parsed.dryRun)
+    case "import-bookmarks":
+        try runImportBookmarks(Array(parsed.positionals.dropFirst()))
+    case "plugin":
+        return try runPlugin(Array(parsed.positionals.dropFirst()), config: config)
+    case "which":
+        try runWhich(Array(parsed.positionals.dropFirst()), config: config)
+    case "render-landing":
+        try runRenderLanding(Array(parsed.positionals.dropFirst()), config: config)
     default:
-        try executeCommand(parsed.positionals, config: config, dryRun: parsed.dryRun)
+        try executeCommand(parsed.positionals, config: config, dryRun: parsed.dryRun, qr: parsed.qr)
     }
 
     return 0
 }
 
-func runServer(config: AppConfig) throws -> Never {
+/// Serves until SIGTERM, SIGINT or the idle timeout has drained the server.
+func runServer(config: AppConfig) throws {
     let pidFile = Paths.pidFile
-    activePidFilePath = pidFile.path
-    try "\(getpid())".write(to: pidFile, atomically: true, encoding: .utf8)
     defer {
-        try? FileManager.default.removeItem(at: pidFile)
+        if activePidFilePath != nil {
+            try? FileManager.default.removeItem(at: pidFile)
+        }
     }
 
+    let router = CommandRouter(config: config)
+    printWarnings(router.aliasShadowWarnings(config: config))
+    printWarnings(router.pluginScanWarnings())
+    printWarnings([config.server.displayURLWarning].compactMap { $0 })
+
     let server = HTTPServer(
         address: config.server.address,
         port: config.server.port,
-        router: CommandRouter(),
+        router: router,
         config: config
     )
-    try server.run()
+    server.stopOnTerminationSignals()
+    // Written only once listening, so `serve --daemon` does not report a server that failed to bind.
+    try server.run {
+        activePidFilePath = pidFile.path
+        do {
+            try writePidFile(getpid(), to: pidFile)
+        } catch {
+            fputs("Warning: could not write \(pidFile.path): \(error.localizedDescription)\n", stderr)
+        }
+    }
+}
+
+func writePidFile(_ pid: pid_t, to file: URL) throws {
+    try "\(pid)".write(to: file, atomically: true, encoding: .utf8)
+}
+
+/// The pid recorded in `file`, or nil when it is missing or does not hold a pid.
+func readPidFile(_ file: URL) -> pid_t? {
+    (try? String(contentsOf: file, encoding: .utf8))
+        .flatMap { pid_t($0.trimmingCharacters(in: .whitespacesAndNewlines)) }
+}
+
+/// Runs `serve` again in a new session, detached from the terminal, with output appended to
+/// `Paths.daemonLogFile`. Returns once the server is listening and has written its pid file, so it can
+/// be stopped with `kill $(cat "$(lolabunny pid-file)")`.
+func startDaemon(serveArguments: [String]) throws -> Int32 {
+    guard let executable = Bundle.main.executablePath else {
+        throw ServerError.message("cannot locate the lolabunny executable")
+    }
+    let logFile = Paths.daemonLogFile
+    let pidFile = Paths.pidFile
+    try? FileManager.default.removeItem(at: pidFile)
+
+    var attributes: posix_spawnattr_t = nil
+    posix_spawnattr_init(&attributes)
+    defer { posix_spawnattr_destroy(&attributes) }
+    posix_spawnattr_setflags(&attributes, Int16(POSIX_SPAWN_SETSID))
+
+    var actions: posix_spawn_file_actions_t = nil
+    posix_spawn_file_actions_init(&actions)
+    defer { posix_spawn_file_actions_destroy(&actions) }
+    posix_spawn_file_actions_addopen(&actions, STDIN_FILENO, "/dev/null", O_RDONLY, 0)
+    posix_spawn_file_actions_addopen(&actions, STDOUT_FILENO, logFile.path, O_WRONLY | O_CREAT | O_APPEND, 0o644)
+    posix_spawn_file_actions_adddup2(&actions, STDOUT_FILENO, STDERR_FILENO)
+
+    let argv = ([executable, "serve"] + serveArguments).map { strdup($0) } + [nil]
+    defer { argv.forEach { free($0) } }
+    var pid: pid_t = 0
+    let status = posix_spawn(&pid, executable, &actions, &attributes, argv, _NSGetEnviron().pointee)
+    guard status == 0 else {
+        throw ServerError.message("failed to start the server in the background: \(String(cString: strerror(status)))")
+    }
+
+    let deadline = Date().addingTimeInterval(10)
+    while Date() < deadline {
+        if readPidFile(pidFile) == pid {
+            print("Lolabunny started in the background (pid \(pid)); logging to \(logFile.path)")
+            return 0
+        }
+        var exitStatus: Int32 = 0
+        if waitpid(pid, &exitStatus, WNOHANG) == pid {
+            throw ServerError.message("the background server exited; see \(logFile.path)")
+        }
+        usleep(50_000)
+    }
+    throw ServerError.message("the background server (pid \(pid)) did not start within 10s; see \(logFile.path)")
+}
+
+/// `history` lists entries newest first; `history replay INDEX` re-runs the entry at that position.
+func runHistory(_ arguments: [String], config: AppConfig, dryRun: Bool) throws {
+    let entries = Array(try History(config: config).entries().reversed())
+    guard arguments.first == "replay" else {
+        for (offset, entry) in entries.enumerated() {
+            let date = Date(timeIntervalSince1970: TimeInterval(entry.timestamp)).formatted(.iso8601)
+            print("\(String(offset + 1).padding(toLength: 5, withPad: " ", startingAt: 0)) \(date)  \(entry.command)")
+        }
+        return
+    }
+
+    guard arguments.count > 1, let index = Int(arguments[1]), entries.indices.contains(index - 1) else {
+        throw ServerError.message("usage: lolabunny history replay INDEX (1-\(entries.count))")
+    }
+    try executeCommand([entries[index - 1].command], config: config, dryRun: dryRun)
+}
+
+func runPlugin(_ arguments: [String], config: AppConfig) throws -> Int32 {
+    switch arguments.first {
+    case "dirs":
+        printPluginDirectories()
+        return 0
+    case "lint":
+        return runPluginLint(config: config)
+    case "test" where arguments.dropFirst().first == "--stdin":
+        try runPluginTest(query: arguments.dropFirst(2).joined(separator: " "), config: config)
+        return 0
+    default:
+        throw ServerError.message("usage: lolabunny plugin dirs|lint|test --stdin ARGS")
+    }
+}
+
+/// Prints the command `arguments` would be routed to, without running it.
+func runWhich(_ arguments: [String], config: AppConfig) throws {
+    guard !arguments.isEmpty else {
+        throw ServerError.message("usage: lolabunny which QUERY")
+    }
+    let route = CommandRouter(config: config).which(arguments.joined(separator: " "), config: config)
+    if let binding = route.binding {
+        print("binding: \(binding)")
+        print("origin:  \(route.origin ?? "")")
+        print("source:  \(route.sourcePath ?? "built in")")
+    } else if route.searchSkipped {
+        print("not searched: shorter than \(config.minSearchLength) characters (--min-search-length)")
+    } else {
+        print("fallback to \(route.location)")
+    }
+}
+
+/// Runs a command read from stdin against `query` and prints the URL, without installing it.
+func runPluginTest(query: String, config: AppConfig) throws {
+    let data = FileHandle.standardInput.readDataToEndOfFile()
+    guard let source = String(data: data, encoding: .utf8), !source.isEmpty else {
+        throw ServerError.message("expected Lua command source on stdin")
+    }
+    let route = try CommandRouter.resolveSource(source, query: query, config: config)
+    if let error = route.error {
+        throw ServerError.message(error)
+    }
+    if !route.matched {
+        fputs("Warning: process returned no URL; showing the search fallback\n", stderr)
+    }
+    print(route.location)
+}
+
+/// Prints lint findings and fails only when there are errors.
+func runPluginLint(config: AppConfig) -> Int32 {
+    let findings = CommandRouter(config: config).lintCommands()
+    for finding in findings {
+        print("\(finding.severity.rawValue): \(finding.path): \(finding.message)")
+    }
+    let errors = findings.filter { $0.severity == .error }.count
+    print("\(errors) error(s), \(findings.count - errors) warning(s)")
+    return errors > 0 ? 1 : 0
+}
+
+/// Lists scanned command directories in precedence order, marking which exist and where new
+/// commands should be written.
+func printPluginDirectories() {
+    let writeDirectory = Paths.userCommandsDirectory.standardizedFileURL.path
+    for directory in Paths.pluginDirectoryCandidates() {
+        let path = directory.standardizedFileURL.path
+        var isDirectory: ObjCBool = false
+        let exists = FileManager.default.fileExists(atPath: path, isDirectory: &isDirectory) && isDirectory.boolValue
+        let status = exists ? "exists" : "missing"
+        let marker = path == writeDirectory ? "write" : ""
+        print("\(status.padding(toLength: 8, withPad: " ", startingAt: 0)) \(marker.padding(toLength: 6, withPad: " ", startingAt: 0)) \(path)")
+    }
+}
+
+/// Writes the bindings page to `--output PATH` (or stdout) so a static server can serve it.
+func runRenderLanding(_ arguments: [String], config: AppConfig) throws {
+    var output: String?
+    var index = 0
+    while index < arguments.count {
+        switch arguments[index] {
+        case "-o", "--output":
+            output = try value(after: arguments[index], in: arguments, index: &index)
+        default:
+            throw ServerError.message("usage: lolabunny render-landing [--output PATH]")
+        }
+    }
+
+    let html = HTTPServer.bindingsHTML(router: CommandRouter(config: config))
+    guard let output else {
+        print(html)
+        return
+    }
+    let url = URL(fileURLWithPath: (output as NSString).expandingTildeInPath)
+    try html.write(to: url, atomically: true, encoding: .utf8)
+    print("Wrote \(url.path)")
+}
+
+func runDoctor(config: AppConfig) -> Int32 {
+    let router = CommandRouter(config: config)
+    let warnings = router.aliasShadowWarnings(config: config)
+        + router.pluginScanWarnings()
+        + [config.server.displayURLWarning].compactMap { $0 }
+    if let hint = homebrewCommandsHint() {
+        print(hint)
+    }
+    guard !warnings.isEmpty else {
+        print("No issues found.")
+        return 0
+    }
+    for warning in warnings {
+        print("Warning: \(warning)")
+    }
+    return 0
+}
+
+/// Points brew users at the brew commands directory when it hasn't been created yet.
+func homebrewCommandsHint() -> String? {
+    let marker = Paths.homebrewHintMarkerFile
+    guard let directory = Paths.missingHomebrewCommandsDirectory(),
+          !FileManager.default.fileExists(atPath: marker.path) else {
+        return nil
+    }
+    try? FileManager.default.createDirectory(at: marker.deletingLastPathComponent(), withIntermediateDirectories: true)
+    FileManager.default.createFile(atPath: marker.path, contents: nil)
+    return "Note: Homebrew is installed; commands installed with brew go in \(directory.path)"
+}
+
+func printWarnings(_ warnings: [String]) {
+    for warning in warnings {
+        fputs("Warning: \(warning)\n", stderr)
+    }
 }
 
 struct ParsedGlobalOptions {
     var dryRun = false
     var list = false
+    var firstRun = false
+    var qr = false
+    var noInteractive = false
     var positionals: [String] = []
 }
 
@@ -103,25 +362,33 @@ func parseGlobalOptions(_ arguments: [String], config: inout AppConfig) throws -
         case "-l", "--list":
             parsed.list = true
             index += 1
-        case "--browser":
-            config.browser = try value(after: argument, in: arguments, index: &index)
-        case "--default-search":
-            config.defaultSearch = try value(after: argument, in: arguments, index: &index)
-        case "--alias":
+        case "--qr":
+            parsed.qr = true
+            index += 1
+        case "--first-run":
+            parsed.firstRun = true
+            index += 1
+        case "--no-interactive":
+            parsed.noInteractive = true
+            index += 1
+        case "--open":
             let raw = try value(after: argument, in: arguments, index: &index)
-            let alias = try parseAlias(raw)
-            config.aliases[alias.key] = alias.value
-        case "--history-enabled":
-            config.history.enabled = try parseBool(value(after: argument, in: arguments, index: &index))
-        case "--history-max-entries":
+            guard let behavior = OpenBehavior(rawValue: raw.lowercased()) else {
+                let choices = OpenBehavior.allCases.map(\.rawValue).joined(separator: ", ")
+                throw ServerError.message("invalid --open: \(raw) (expected one of \(choices))")
+            }
+            config.openBehavior = behavior
+        case "--open-timeout":
             let raw = try value(after: argument, in: arguments, index: &index)
-            guard let count = Int(raw), count > 0 else {
-                throw ServerError.message("invalid --history-max-entries: \(raw)")
+            guard let seconds = TimeInterval(raw), seconds > 0 else {
+                throw ServerError.message("invalid --open-timeout: \(raw)")
             }
-            config.history.maxEntries = count
+            config.openTimeout = seconds
         default:
-            parsed.positionals.append(argument)
-            index += 1
+            if try !applySharedOption(argument, in: arguments, index: &index, to: &config) {
+                parsed.positionals.append(argument)
+                index += 1
+            }
         }
     }
     return parsed
@@ -144,28 +411,166 @@ func applyServeOptions(_ arguments: [String], to config: inout AppConfig) throws
             config.server.volumePath = try value(after: argument, in: arguments, index: &index)
         case "--log-level":
             config.server.logLevel = try value(after: argument, in: arguments, index: &index)
-        case "--default-search":
-            config.defaultSearch = try value(after: argument, in: arguments, index: &index)
-        case "--history-enabled":
-            config.history.enabled = try parseBool(value(after: argument, in: arguments, index: &index))
-        case "--history-max-entries":
+        case "--not-found-template":
+            config.server.notFoundTemplate = try value(after: argument, in: arguments, index: &index)
+        case "--root-redirect":
+            config.server.rootRedirect = try value(after: argument, in: arguments, index: &index)
+        case "--ident":
+            config.server.ident = try value(after: argument, in: arguments, index: &index)
+        case "--debug-endpoints":
+            config.server.debugEndpoints = try parseBool(value(after: argument, in: arguments, index: &index))
+        case "--security-headers":
+            config.server.securityHeaders = try parseBool(value(after: argument, in: arguments, index: &index))
+        case "--workers":
             let raw = try value(after: argument, in: arguments, index: &index)
-            guard let count = Int(raw), count > 0 else {
-                throw ServerError.message("invalid --history-max-entries: \(raw)")
+            guard let workers = Int(raw), workers >= 1 else {
+                throw ServerError.message("invalid --workers: \(raw) (must be at least 1)")
+            }
+            config.server.workers = workers
+        case "--port-retry":
+            let raw = try value(after: argument, in: arguments, index: &index)
+            guard let retries = Int(raw), retries >= 0 else {
+                throw ServerError.message("invalid --port-retry: \(raw)")
+            }
+            config.server.portRetries = retries
+        case "--shutdown-grace":
+            let raw = try value(after: argument, in: arguments, index: &index)
+            guard let seconds = TimeInterval(raw), seconds >= 0 else {
+                throw ServerError.message("invalid --shutdown-grace: \(raw)")
             }
-            config.history.maxEntries = count
-        case "--alias":
+            config.server.shutdownGrace = seconds
+        case "--shutdown-mercy":
             let raw = try value(after: argument, in: arguments, index: &index)
-            let alias = try parseAlias(raw)
-            config.aliases[alias.key] = alias.value
-        case "--browser":
-            config.browser = try value(after: argument, in: arguments, index: &index)
+            guard let seconds = TimeInterval(raw), seconds >= 0 else {
+                throw ServerError.message("invalid --shutdown-mercy: \(raw)")
+            }
+            config.server.shutdownMercy = seconds
+        case "--client-timeout":
+            let raw = try value(after: argument, in: arguments, index: &index)
+            guard let seconds = TimeInterval(raw), seconds >= 0 else {
+                throw ServerError.message("invalid --client-timeout: \(raw)")
+            }
+            config.server.clientTimeout = seconds
+        case "--idle-timeout":
+            let raw = try value(after: argument, in: arguments, index: &index)
+            guard let seconds = TimeInterval(raw), seconds > 0 else {
+                throw ServerError.message("invalid --idle-timeout: \(raw) (must be greater than 0)")
+            }
+            config.server.idleTimeout = seconds
+        case "--suggest-limit":
+            let raw = try value(after: argument, in: arguments, index: &index)
+            guard let count = Int(raw), count > 0 else {
+                throw ServerError.message("invalid --suggest-limit: \(raw)")
+            }
+            config.server.suggestLimit = count
+        case "--unix-socket":
+            config.server.unixSocket = (try value(after: argument, in: arguments, index: &index) as NSString)
+                .expandingTildeInPath
+        case "--history-async":
+            config.history.asyncWrites = try parseBool(value(after: argument, in: arguments, index: &index))
         default:
-            index += 1
+            if try !applySharedOption(argument, in: arguments, index: &index, to: &config) {
+                index += 1
+            }
         }
     }
 }
 
+/// Applies `argument` when it is one of the `AppConfig` options that both `serve` and the CLI accept,
+/// advancing `index` past it and its value. Returns false, leaving `index` alone, for any other argument.
+func applySharedOption(
+    _ argument: String,
+    in arguments: [String],
+    index: inout Int,
+    to config: inout AppConfig
+) throws -> Bool {
+    switch argument {
+    case "--safe-mode":
+        config.safeMode = true
+        index += 1
+    case "--browser":
+        config.browser = try value(after: argument, in: arguments, index: &index)
+    case "--default-search":
+        config.defaultSearch = try value(after: argument, in: arguments, index: &index)
+    case "--search-privacy":
+        config.searchPrivacy = try parseBool(value(after: argument, in: arguments, index: &index))
+    case "--ddg-bangs":
+        config.ddgBangs = try parseBool(value(after: argument, in: arguments, index: &index))
+    case "--kagi-token-env":
+        config.kagiTokenEnv = try value(after: argument, in: arguments, index: &index)
+    case "--search-override":
+        let raw = try value(after: argument, in: arguments, index: &index)
+        let override = try parseAlias(raw, kind: "search override")
+        guard override.value.contains("%s") else {
+            throw ServerError.message("search override template must contain %s, got '\(override.value)'")
+        }
+        config.searchOverrides[override.key.lowercased()] = override.value
+    case "--alias":
+        let raw = try value(after: argument, in: arguments, index: &index)
+        let alias = try parseAlias(raw)
+        config.aliases[alias.key] = alias.value
+    case "--default-args":
+        let raw = try value(after: argument, in: arguments, index: &index)
+        let defaults = try parseAlias(raw, kind: "default args")
+        config.defaultArguments[defaults.key.lowercased()] = defaults.value
+    case "--command-description":
+        let raw = try value(after: argument, in: arguments, index: &index)
+        let override = try parseAlias(raw, kind: "command description")
+        config.commandMeta[override.key.lowercased(), default: CommandMetaOverride()].description = override.value
+    case "--command-example":
+        let raw = try value(after: argument, in: arguments, index: &index)
+        let override = try parseAlias(raw, kind: "command example")
+        config.commandMeta[override.key.lowercased(), default: CommandMetaOverride()].example = override.value
+    case "--clean-url-param":
+        config.cleanURLParams.append(try value(after: argument, in: arguments, index: &index))
+    case "--command-prefix":
+        config.commandPrefix = try value(after: argument, in: arguments, index: &index)
+    case "--min-search-length":
+        let raw = try value(after: argument, in: arguments, index: &index)
+        guard let length = Int(raw), length >= 0 else {
+            throw ServerError.message("invalid --min-search-length: \(raw)")
+        }
+        config.minSearchLength = length
+    case "--public-url":
+        config.server.publicURL = try value(after: argument, in: arguments, index: &index)
+    case "--public-url-scheme":
+        let raw = try value(after: argument, in: arguments, index: &index).lowercased()
+        guard ["http", "https"].contains(raw) else {
+            throw ServerError.message("invalid --public-url-scheme: \(raw) (expected http or https)")
+        }
+        config.server.publicURLScheme = raw
+    case "--history-enabled":
+        config.history.enabled = try parseBool(value(after: argument, in: arguments, index: &index))
+    case "--history-max-entries":
+        let raw = try value(after: argument, in: arguments, index: &index)
+        guard let count = Int(raw), count > 0 else {
+            throw ServerError.message("invalid --history-max-entries: \(raw)")
+        }
+        config.history.maxEntries = count
+    case "--history-dedup":
+        config.history.dedup = try parseBool(value(after: argument, in: arguments, index: &index))
+    case "--history-store-url":
+        config.history.storeURL = try parseBool(value(after: argument, in: arguments, index: &index))
+    case "--history-encryption-key-env":
+        config.history.encryptionKeyEnv = try value(after: argument, in: arguments, index: &index)
+    case "--plugins-max-depth":
+        let raw = try value(after: argument, in: arguments, index: &index)
+        guard let depth = Int(raw), depth >= 1 else {
+            throw ServerError.message("invalid --plugins-max-depth: \(raw) (must be at least 1)")
+        }
+        config.pluginScan.maxDepth = depth
+    case "--plugins-max-files":
+        let raw = try value(after: argument, in: arguments, index: &index)
+        guard let count = Int(raw), count >= 1 else {
+            throw ServerError.message("invalid --plugins-max-files: \(raw) (must be at least 1)")
+        }
+        config.pluginScan.maxFiles = count
+    default:
+        return false
+    }
+    return true
+}
+
 func value(after flag: String, in arguments: [String], index: inout Int) throws -> String {
     let valueIndex = index + 1
     guard valueIndex < arguments.count else {
@@ -175,19 +580,32 @@ func value(after flag: String, in arguments: [String], index: inout Int) throws
     return arguments[valueIndex]
 }
 
-func parseAlias(_ raw: String) throws -> (key: String, value: String) {
+func parseAlias(_ raw: String, kind: String = "alias") throws -> (key: String, value: String) {
     let parts = raw.split(separator: "=", maxSplits: 1, omittingEmptySubsequences: false)
     guard parts.count == 2 else {
-        throw ServerError.message("alias must be KEY=VALUE, got '\(raw)'")
+        throw ServerError.message("\(kind) must be KEY=VALUE, got '\(raw)'")
     }
     let key = parts[0].trimmingCharacters(in: .whitespacesAndNewlines)
     let value = parts[1].trimmingCharacters(in: .whitespacesAndNewlines)
     guard !key.isEmpty, !value.isEmpty else {
-        throw ServerError.message("alias must be KEY=VALUE, got '\(raw)'")
+        throw ServerError.message("\(kind) must be KEY=VALUE, got '\(raw)'")
     }
     return (key, value)
 }
 
+/// Defaults for both `serve` and the CLI, before flags: `LOLABUNNY_SAFE_MODE` turns on `--safe-mode`.
+/// An unrecognized value is an error rather than off, since safe mode is how a broken setup is recovered.
+func environmentConfig() throws -> AppConfig {
+    var config = AppConfig()
+    if let raw = ProcessInfo.processInfo.environment["LOLABUNNY_SAFE_MODE"] {
+        guard let safeMode = try? parseBool(raw) else {
+            throw ServerError.message("invalid LOLABUNNY_SAFE_MODE: \(raw)")
+        }
+        config.safeMode = safeMode
+    }
+    return config
+}
+
 func parseBool(_ raw: String) throws -> Bool {
     switch raw.lowercased() {
     case "1", "true", "yes", "on":
@@ -199,42 +617,146 @@ func parseBool(_ raw: String) throws -> Bool {
     }
 }
 
-func executeCommand(_ args: [String], config: AppConfig, dryRun: Bool) throws {
+func executeCommand(_ args: [String], config: AppConfig, dryRun: Bool, qr: Bool = false) throws {
     let fullArgs = args.joined(separator: " ")
-    let url = CommandRouter().route(fullArgs, config: config)
-    print(url)
+    let router = CommandRouter(config: config)
+    printWarnings(router.aliasShadowWarnings(config: config, query: fullArgs))
+    let route = router.resolveRoute(fullArgs, config: config)
+    if let error = route.error {
+        throw ServerError.message(error)
+    }
+    if route.searchSkipped {
+        fputs("No command matches '\(fullArgs)', and queries shorter than \(config.minSearchLength) characters "
+            + "are not searched (--min-search-length)\n", stderr)
+        return
+    }
+    for url in route.locations {
+        print(url)
+        if qr {
+            guard let lines = qrCodeLines(for: url) else {
+                throw ServerError.message("failed to render QR code")
+            }
+            print(lines.joined(separator: "\n"))
+        }
+    }
 
     if config.history.enabled {
-        History(config: config).add(command: fullArgs, user: NSUserName())
+        History(config: config).add(command: fullArgs, user: NSUserName(), url: route.location)
     }
 
-    if !dryRun {
-        try openURL(url, browser: config.browser)
+    if !dryRun, config.openBehavior.shouldOpen(stdoutIsTTY: isatty(STDOUT_FILENO) == 1) {
+        for url in route.locations {
+            try openURL(url, browser: config.browser, timeout: config.openTimeout)
+        }
     }
 }
 
-func openURL(_ url: String, browser: String?) throws {
+/// Opens `url` with the `open` found on `PATH`, normally `/usr/bin/open`.
+/// Opens `url` with `/usr/bin/open`. Tests point `LOLABUNNY_TEST_OPEN_COMMAND` at a stub launcher instead.
+func openURL(_ url: String, browser: String?, timeout: TimeInterval) throws {
     let process = Process()
-    process.executableURL = URL(fileURLWithPath: "/usr/bin/open")
+    let launcher = ProcessInfo.processInfo.environment["LOLABUNNY_TEST_OPEN_COMMAND"] ?? "/usr/bin/open"
+    process.executableURL = URL(fileURLWithPath: launcher)
     if let browser, !browser.trimmingCharacters(in: .whitespacesAndNewlines).isEmpty {
         process.arguments = ["-a", browser, url]
     } else {
         process.arguments = [url]
     }
-    try process.run()
-    process.waitUntilExit()
-    guard process.terminationStatus == 0 else {
+    guard let status = try runProcess(process, timeout: timeout) else {
+        fputs("Warning: browser launcher did not finish within \(Int(timeout))s; stopped it\n", stderr)
+        return
+    }
+    guard status == 0 else {
         throw ServerError.message("failed to open URL")
     }
 }
 
+/// Runs `process` and returns its exit status, or nil when it is still running after `timeout`. A
+/// process that times out is sent SIGTERM and given a second to exit, so it isn't left behind.
+func runProcess(_ process: Process, timeout: TimeInterval) throws -> Int32? {
+    let exited = DispatchSemaphore(value: 0)
+    process.terminationHandler = { _ in
+        exited.signal()
+    }
+    try process.run()
+    guard exited.wait(timeout: .now() + timeout) == .success else {
+        process.terminate()
+        _ = exited.wait(timeout: .now() + 1)
+        return nil
+    }
+    return process.terminationStatus
+}
+
+func runOnboarding(interactive: Bool, config: AppConfig) throws {
+    let commandsDirectory = Paths.userCommandsDirectory
+    let noneFound = CommandRouter(config: config).allCommands().isEmpty ? "No commands were found. " : ""
+    print("""
+    Welcome to Lolabunny.
+
+    \(noneFound)Commands are Lua files loaded from:
+      \(commandsDirectory.path)
+
+    """)
+
+    if interactive, confirm("Install a sample `gh` command there?") {
+        try FileManager.default.createDirectory(at: commandsDirectory, withIntermediateDirectories: true)
+        let sample = commandsDirectory.appendingPathComponent("gh.lua")
+        if FileManager.default.fileExists(atPath: sample.path) {
+            print("Skipped: \(sample.path) already exists.")
+        } else {
+            try sampleCommandSource.write(to: sample, atomically: true, encoding: .utf8)
+            print("Created \(sample.path)")
+        }
+        print("")
+    }
+
+    print("""
+    Next steps:
+      1. Install the standard commands: brew install sidosera/lolacore/lolacore
+      2. Start the server: lolabunny serve
+      3. Set your browser search engine to: \(config.server.displayURL)/?cmd=%s
+      4. Try it: lolabunny --dry-run gh lolabunny
+    """)
+}
+
+func confirm(_ question: String) -> Bool {
+    print("\(question) [y/N] ", terminator: "")
+    fflush(stdout)
+    guard let answer = readLine()?.trimmingCharacters(in: .whitespacesAndNewlines).lowercased() else {
+        return false
+    }
+    return answer == "y" || answer == "yes"
+}
+
+var sampleCommandSource: String {
+    """
+    function process(full_args)
+      local args = get_args(full_args, "gh")
+      if args == "" then
+        return "https://github.com"
+      end
+      return "https://github.com/search?q=" .. url_encode(args)
+    end
+
+    return {
+      bindings = { "gh" },
+      description = "Search GitHub",
+      example = "gh lolabunny"
+    }
+
+    """
+}
+
 func printCommands(_ commands: [CommandInfo]) {
+    if let hint = homebrewCommandsHint() {
+        fputs("\(hint)\n", stderr)
+    }
     let rows = commands.map { command in
         let aliases = command.bindings.dropFirst().joined(separator: ", ")
         return (
             command: command.bindings.first ?? "",
             aliases: aliases.isEmpty ? "-" : aliases,
-            description: command.description,
+            description: [command.description, commandProvenance(command)].compactMap { $0 }.joined(separator: " "),
             example: command.example
         )
     }
@@ -248,14 +770,71 @@ func printCommands(_ commands: [CommandInfo]) {
     print("")
 }
 
+/// Lists the URL each command produces for the argument `{}`.
+func printCommandTemplates(_ templates: [(command: CommandInfo, template: String?)]) {
+    print("")
+    print("Command          Template")
+    print("-------          --------")
+    for (command, template) in templates {
+        print("\((command.bindings.first ?? "").padding(toLength: 16, withPad: " ", startingAt: 0)) \(template ?? "-")")
+    }
+    print("")
+}
+
+/// e.g. `(v1.2.0 by Jane Doe)`, or nil when the command declares neither.
+func commandProvenance(_ command: CommandInfo) -> String? {
+    let parts = [command.version.map { "v\($0)" }, command.author.map { "by \($0)" }].compactMap { $0 }
+    return parts.isEmpty ? nil : "(\(parts.joined(separator: " ")))"
+}
+
 func printHelp() {
     print("""
     Lightweight local command router.
 
     Usage:
-      lolabunny serve [--port PORT] [--address ADDRESS]
-      lolabunny bindings
-      lolabunny [--dry-run] [BINDING] [ARGS]
+      lolabunny serve [--daemon] [--port PORT] [--address ADDRESS] [--workers COUNT] [--shutdown-grace SECONDS]
+      lolabunny serve --unix-socket PATH
+      lolabunny bindings [--templates | --bookmarks]
+      lolabunny doctor
+      lolabunny which QUERY
+      lolabunny history [replay INDEX]
+      lolabunny import-bookmarks FILE
+      lolabunny plugin dirs|lint
+      lolabunny plugin test --stdin [BINDING] [ARGS]
+      lolabunny render-landing [--output PATH]
+      lolabunny [--dry-run] [--qr] [--open always|never|auto] [--open-timeout SECONDS] [BINDING] [ARGS]
+      lolabunny --first-run [--no-interactive]
+
+    Options for serve and the CLI:
+      --safe-mode                       load no commands
+      --browser NAME                    open URLs in this browser
+      --default-search ENGINE           search used when no command matches
+      --search-privacy true|false       add each engine's privacy parameters to search URLs
+      --ddg-bangs true|false            resolve DuckDuckGo !bangs (default true)
+      --kagi-token-env VAR              environment variable holding the Kagi token
+      --search-override WORD=URL        search URL with %s for queries starting with WORD
+      --alias NAME=EXPANSION            expand NAME before resolving
+      --default-args BINDING=ARGS       arguments used when BINDING is given none
+      --command-description BINDING=TEXT
+      --command-example BINDING=TEXT    override a command's description or example
+      --clean-url-param PATTERN         drop matching query parameters from URLs
+      --command-prefix PREFIX           only treat queries starting with PREFIX as commands
+      --min-search-length COUNT         do not search unmatched queries shorter than COUNT
+      --public-url URL                  URL the server is reached at
+      --public-url-scheme http|https    scheme of the public URL
+      --history-enabled true|false      record resolved queries
+      --history-max-entries COUNT       entries kept in history
+      --history-dedup true|false        keep only the latest of repeated queries
+      --history-store-url true|false    record the resolved URL too
+      --history-encryption-key-env VAR  encrypt history with the passphrase in VAR
+      --plugins-max-depth LEVELS        directory levels scanned per plugin directory (default 8)
+      --plugins-max-files COUNT         command files loaded in total (default 10000)
+
+    Options for serve only:
+      --volume-path PATH, --log-level LEVEL, --not-found-template PATH, --root-redirect URL,
+      --ident NAME, --debug-endpoints true|false, --security-headers true|false,
+      --port-retry COUNT, --shutdown-mercy SECONDS, --client-timeout SECONDS,
+      --idle-timeout SECONDS, --suggest-limit COUNT, --history-async true|false
     """)
 }
 
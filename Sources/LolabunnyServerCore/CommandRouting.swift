@@ -1,9 +1,18 @@
+import CommonCrypto
+import CryptoKit
 import Foundation
 import LuaSwift
+import zlib
 
 public struct EmbeddedCommandExecutor {
     public init() {}
 
+    /// Resolves `command` to a URL for the host to open itself, e.g. from a global hotkey prompt.
+    /// Nothing is opened and no history is recorded.
+    public func resolve(_ command: String, config: AppConfig = AppConfig()) -> String {
+        CommandRouter(config: config).route(command, config: config)
+    }
+
     public func location(
         for command: String,
         defaultSearch: String,
@@ -22,9 +31,9 @@ public struct EmbeddedCommandExecutor {
         config.server.port = port
         config.server.volumePath = volumePath
 
-        let location = CommandRouter().route(command, config: config)
+        let location = CommandRouter(config: config).route(command, config: config)
         if config.history.enabled {
-            History(config: config).add(command: command, user: user)
+            History(config: config).add(command: command, user: user, url: location)
         }
         return location
     }
@@ -33,10 +42,42 @@ public struct EmbeddedCommandExecutor {
 public struct HistoryConfig {
     public var enabled: Bool
     public var maxEntries: Int
+    /// Move a repeated query to the end of the history instead of appending a duplicate.
+    public var dedup: Bool
+    /// Also record the resolved URL of each query.
+    public var storeURL: Bool
+    /// Name of an environment variable holding a passphrase; when set, entries are encrypted at rest.
+    public var encryptionKeyEnv: String?
+    /// Let the server write entries on a background queue instead of while answering the request.
+    /// Pending writes are finished on shutdown. The CLI always writes before exiting.
+    public var asyncWrites: Bool
 
-    public init(enabled: Bool = true, maxEntries: Int = 1_000) {
+    public init(
+        enabled: Bool = true,
+        maxEntries: Int = 1_000,
+        dedup: Bool = false,
+        storeURL: Bool = false,
+        encryptionKeyEnv: String? = nil,
+        asyncWrites: Bool = true
+    ) {
         self.enabled = enabled
         self.maxEntries = maxEntries
+        self.dedup = dedup
+        self.storeURL = storeURL
+        self.encryptionKeyEnv = encryptionKeyEnv
+        self.asyncWrites = asyncWrites
+    }
+}
+
+public struct PluginScanConfig {
+    /// Directory levels scanned below each plugin directory; files directly inside it are level 1.
+    public var maxDepth: Int
+    /// Command files loaded across all plugin directories.
+    public var maxFiles: Int
+
+    public init(maxDepth: Int = 8, maxFiles: Int = 10_000) {
+        self.maxDepth = maxDepth
+        self.maxFiles = maxFiles
     }
 }
 
@@ -45,60 +86,371 @@ public struct ServerConfig {
     public var address: String
     public var logLevel: String
     public var volumePath: String?
+    /// HTML file served with a 404 for unknown paths. `__PATH__` is replaced with the requested path.
+    public var notFoundTemplate: String?
+    /// Maximum number of requests handled concurrently. Nil leaves it unbounded.
+    public var workers: Int?
+    /// How many following ports to try when `port` is already in use.
+    public var portRetries: Int
+    /// Public URL users type into their browser, e.g. `bunny.example.com` or `http://box.local:8085`.
+    public var publicURL: String?
+    /// Where a visit to `/` without a command is redirected instead of showing the bindings page.
+    public var rootRedirect: String?
+    /// Value of the `Server` response header. Defaults to `Lolabunny/<version>`.
+    public var ident: String?
+    /// Enables debugging aids such as the `X-Lolabunny-Matched` header on command redirects.
+    public var debugEndpoints: Bool
+    /// Seconds in-flight requests get to finish after SIGTERM/SIGINT before their sockets are shut down.
+    public var shutdownGrace: TimeInterval
+    /// Further seconds allowed after `shutdownGrace` for shut-down connections to close before exiting.
+    public var shutdownMercy: TimeInterval
+    /// Adds `Content-Security-Policy`, `X-Content-Type-Options` and `Referrer-Policy` to HTML pages.
+    public var securityHeaders: Bool
+    /// Seconds a client connection may stall while sending its request or receiving the response
+    /// before it is dropped; 0 waits forever. Connections are closed after each response.
+    public var clientTimeout: TimeInterval
+    /// When set, the server shuts down gracefully after this many seconds without a request.
+    public var idleTimeout: TimeInterval?
+    /// Listen on this Unix domain socket instead of TCP; `port`, `address` and `portRetries` are then unused.
+    public var unixSocket: String?
+    /// Most completions `/suggest` returns, commands whose binding starts with the query first.
+    public var suggestLimit: Int
+    /// Scheme given to a bare `publicURL` host instead of inferring one, e.g. `https` for internal TLS
+    /// on a `.local` name. A scheme written in `publicURL` itself still wins.
+    public var publicURLScheme: String?
 
     public init(
         port: UInt16 = 18_085,
         address: String = "127.0.0.1",
         logLevel: String = "normal",
-        volumePath: String? = nil
+        volumePath: String? = nil,
+        notFoundTemplate: String? = nil,
+        workers: Int? = nil,
+        portRetries: Int = 0,
+        publicURL: String? = nil,
+        rootRedirect: String? = nil,
+        ident: String? = nil,
+        debugEndpoints: Bool = false,
+        shutdownGrace: TimeInterval = 5,
+        shutdownMercy: TimeInterval = 1,
+        securityHeaders: Bool = true,
+        clientTimeout: TimeInterval = 5,
+        idleTimeout: TimeInterval? = nil,
+        unixSocket: String? = nil,
+        suggestLimit: Int = 10,
+        publicURLScheme: String? = nil
     ) {
         self.port = port
         self.address = address
         self.logLevel = logLevel
         self.volumePath = volumePath
+        self.notFoundTemplate = notFoundTemplate
+        self.workers = workers
+        self.portRetries = portRetries
+        self.publicURL = publicURL
+        self.rootRedirect = rootRedirect
+        self.ident = ident
+        self.debugEndpoints = debugEndpoints
+        self.shutdownGrace = shutdownGrace
+        self.shutdownMercy = shutdownMercy
+        self.securityHeaders = securityHeaders
+        self.clientTimeout = clientTimeout
+        self.idleTimeout = idleTimeout
+        self.unixSocket = unixSocket
+        self.suggestLimit = suggestLimit
+        self.publicURLScheme = publicURLScheme
     }
 
+    /// `publicURL` without a trailing slash. Bare hosts get `publicURLScheme` when set, otherwise
+    /// `http://` when local and `https://` otherwise.
     public var displayURL: String {
-        "http://localhost:\(port)"
+        guard let raw = publicURL?.trimmingCharacters(in: .whitespacesAndNewlines), !raw.isEmpty else {
+            return "http://localhost:\(port)"
+        }
+        var url = raw
+        while url.hasSuffix("/") {
+            url.removeLast()
+        }
+        if url.contains("://") {
+            return url
+        }
+        if let scheme = publicURLScheme?.nilIfEmpty {
+            return "\(scheme)://\(url)"
+        }
+        return (isLocalHost(hostName(of: url)) ? "http://" : "https://") + url
+    }
+
+    /// A hint when `publicURL` looks unusual, e.g. a public domain with an explicit port.
+    public var displayURLWarning: String? {
+        guard let raw = publicURL?.trimmingCharacters(in: .whitespacesAndNewlines), !raw.isEmpty,
+              !raw.contains("://"), publicURLScheme?.nilIfEmpty == nil else {
+            return nil
+        }
+        let hostAndPort = raw.split(separator: "/", maxSplits: 1).first.map(String.init) ?? raw
+        guard hostAndPort.contains(":"), !isLocalHost(hostName(of: raw)) else {
+            return nil
+        }
+        return "public URL '\(raw)' has a port on a public domain and was given https://; "
+            + "write the scheme explicitly if the server is reached over plain http"
+    }
+
+    private func hostName(of url: String) -> String {
+        let hostAndPort = url.split(separator: "/", maxSplits: 1).first.map(String.init) ?? url
+        return (hostAndPort.split(separator: ":").first.map(String.init) ?? hostAndPort).lowercased()
+    }
+
+    private func isLocalHost(_ host: String) -> Bool {
+        host == "localhost"
+            || host.hasSuffix(".local")
+            || host.hasSuffix(".localhost")
+            || !host.contains(".")
+            || host.allSatisfy { $0.isNumber || $0 == "." }
+    }
+}
+
+/// Whether the CLI opens the resolved URL in a browser or only prints it.
+public enum OpenBehavior: String, CaseIterable {
+    case always
+    case never
+    /// Open only when stdout is a terminal, so `lolabunny gh foo | pbcopy` just prints.
+    case auto
+
+    public func shouldOpen(stdoutIsTTY: Bool) -> Bool {
+        switch self {
+        case .always:
+            return true
+        case .never:
+            return false
+        case .auto:
+            return stdoutIsTTY
+        }
     }
 }
 
 public struct AppConfig {
     public var browser: String?
+    public var openBehavior: OpenBehavior
+    /// Seconds to wait for the browser launcher before returning control to the terminal.
+    public var openTimeout: TimeInterval
     public var defaultSearch: String
+    /// Add each engine's privacy parameters to search URLs; see `searchPrivacyParameters(for:)`.
+    public var searchPrivacy: Bool
+    /// Send unmatched `!bang` queries such as `!w rust` to DuckDuckGo's bang redirects.
+    public var ddgBangs: Bool
+    /// Search URL templates used instead of `defaultSearch`, keyed by the query's lowercased first word.
+    /// `%s` in the template is replaced with the rest of the query, percent-encoded.
+    public var searchOverrides: [String: String]
+    /// Name of an environment variable holding a Kagi session link token, added to Kagi searches so they
+    /// work without being signed in.
+    public var kagiTokenEnv: String?
     public var aliases: [String: String]
+    /// Arguments appended whenever a binding is used, e.g. `gh` → `org:myco`. See `applyingDefaultArguments`.
+    public var defaultArguments: [String: String]
+    /// Description and example overrides for listings, keyed by lowercased binding.
+    public var commandMeta: [String: CommandMetaOverride]
+    /// When set, only queries starting with this prefix (e.g. `!`) are routed to commands; everything else is searched.
+    public var commandPrefix: String?
+    /// Queries no command handles are only searched when at least this many characters long;
+    /// shorter ones resolve to nothing, so a stray keystroke isn't sent to the search engine.
+    public var minSearchLength: Int
+    /// Query parameter names stripped from resolved URLs. A trailing `*` matches by prefix (e.g. `utm_*`).
+    public var cleanURLParams: [String]
     public var history: HistoryConfig
     public var server: ServerConfig
+    public var pluginScan: PluginScanConfig
+    /// Load no commands at all, to rule them out while debugging.
+    public var safeMode: Bool
 
     public init(
         browser: String? = nil,
+        openBehavior: OpenBehavior = .always,
+        openTimeout: TimeInterval = 5,
         defaultSearch: String = "google",
+        searchPrivacy: Bool = false,
+        ddgBangs: Bool = true,
+        searchOverrides: [String: String] = [:],
+        kagiTokenEnv: String? = nil,
         aliases: [String: String] = [:],
+        defaultArguments: [String: String] = [:],
+        commandMeta: [String: CommandMetaOverride] = [:],
+        commandPrefix: String? = nil,
+        minSearchLength: Int = 0,
+        cleanURLParams: [String] = [],
         history: HistoryConfig = HistoryConfig(),
-        server: ServerConfig = ServerConfig()
+        server: ServerConfig = ServerConfig(),
+        pluginScan: PluginScanConfig = PluginScanConfig(),
+        safeMode: Bool = false
     ) {
         self.browser = browser
+        self.openBehavior = openBehavior
+        self.openTimeout = openTimeout
         self.defaultSearch = defaultSearch
+        self.searchPrivacy = searchPrivacy
+        self.ddgBangs = ddgBangs
+        self.searchOverrides = searchOverrides
+        self.kagiTokenEnv = kagiTokenEnv
         self.aliases = aliases
+        self.defaultArguments = defaultArguments
+        self.commandMeta = commandMeta
+        self.commandPrefix = commandPrefix
+        self.minSearchLength = minSearchLength
+        self.cleanURLParams = cleanURLParams
         self.history = history
         self.server = server
+        self.pluginScan = pluginScan
+        self.safeMode = safeMode
     }
 
+    /// The query with the command prefix removed, or nil when a prefix is configured and missing.
+    public func commandQuery(from query: String) -> String? {
+        guard let commandPrefix, !commandPrefix.isEmpty else {
+            return query
+        }
+        let trimmed = query.trimmingCharacters(in: .whitespacesAndNewlines)
+        guard trimmed.hasPrefix(commandPrefix) else {
+            return nil
+        }
+        return String(trimmed.dropFirst(commandPrefix.count))
+    }
+
+    /// Expands an alias keyed by the whole query or by its first token. The remaining arguments are
+    /// substituted for `$*` and `$1`, `$2`, ... in the alias value, or appended when it has no placeholders.
     public func resolveCommand(_ command: String) -> String {
-        aliases[command] ?? command
+        if let expansion = aliases[command] {
+            return expansion
+        }
+        let name = commandName(from: command)
+        guard let expansion = aliases[name] else {
+            return command
+        }
+        let rest = arguments(after: name, in: command)
+        if let substituted = substituteAliasPlaceholders(in: expansion, arguments: rest) {
+            return substituted
+        }
+        return rest.isEmpty ? expansion : "\(expansion) \(rest)"
+    }
+
+    /// Appends the binding's default arguments to `query`. Explicit arguments win: a default word is
+    /// dropped when the query already has it, or has a word with the same `key:` prefix (`org:other`
+    /// overrides a default `org:myco`).
+    public func applyingDefaultArguments(to query: String) -> String {
+        let binding = commandName(from: query)
+        guard let defaults = defaultArguments[binding.lowercased()] ?? defaultArguments[binding] else {
+            return query
+        }
+        let explicit = arguments(after: binding, in: query).split(whereSeparator: \.isWhitespace).map(String.init)
+        let explicitKeys = Set(explicit.compactMap(Self.argumentKey))
+        let added = defaults.split(whereSeparator: \.isWhitespace).map(String.init).filter { word in
+            if let key = Self.argumentKey(word) {
+                return !explicitKeys.contains(key)
+            }
+            return !explicit.contains(word)
+        }
+        return ([binding] + explicit + added).joined(separator: " ")
     }
 
-    public func searchURL(for query: String) -> String {
+    private static func argumentKey(_ word: String) -> String? {
+        guard let colon = word.firstIndex(of: ":"), colon != word.startIndex else {
+            return nil
+        }
+        return word[...colon].lowercased()
+    }
+
+    /// Where `query` is searched: `engine` when given, else a `searchOverrides` template matching its
+    /// first word, else `defaultSearch`.
+    public func searchURL(for query: String, engine: String? = nil) -> String {
+        let name = commandName(from: query.trimmingCharacters(in: .whitespacesAndNewlines))
+        if engine == nil, let template = searchOverrides[name.lowercased()] {
+            return template.replacingOccurrences(of: "%s", with: percentEncode(arguments(after: name, in: query)))
+        }
         let encoded = percentEncode(query)
-        switch defaultSearch.lowercased() {
+        let engineName = (engine ?? defaultSearch).lowercased()
+        let privacy = searchPrivacy ? Self.searchPrivacyParameters(for: engineName).map { "&\($0)" }.joined() : ""
+        switch engineName {
         case "ddg", "duckduckgo":
-            return "https://duckduckgo.com/?q=\(encoded)"
+            return "https://duckduckgo.com/?q=\(encoded)\(privacy)"
         case "bing":
-            return "https://www.bing.com/search?q=\(encoded)"
+            return "https://www.bing.com/search?q=\(encoded)\(privacy)"
+        case "brave":
+            return "https://search.brave.com/search?q=\(encoded)\(privacy)"
+        case "kagi":
+            let token = kagiTokenEnv
+                .flatMap { ProcessInfo.processInfo.environment[$0]?.nilIfEmpty }
+                .map { "&token=\(percentEncode($0))" } ?? ""
+            return "https://kagi.com/search?q=\(encoded)\(token)\(privacy)"
+        case "startpage":
+            return "https://www.startpage.com/sp/search?query=\(encoded)\(privacy)"
+        case "ecosia":
+            return "https://www.ecosia.org/search?q=\(encoded)\(privacy)"
         default:
-            return "https://www.google.com/search?q=\(encoded)"
+            return "https://www.google.com/search?q=\(encoded)\(privacy)"
+        }
+    }
+
+    /// DuckDuckGo's redirect for a `!bang rest` query, or nil when `query` is no bang. A bare `!` is no bang.
+    public func bangSearchURL(for query: String) -> String? {
+        let trimmed = query.trimmingCharacters(in: .whitespacesAndNewlines)
+        guard trimmed.hasPrefix("!") else {
+            return nil
+        }
+        let bang = commandName(from: String(trimmed.dropFirst()))
+        guard !bang.isEmpty else {
+            return nil
         }
+        let rest = arguments(after: bang, in: String(trimmed.dropFirst()))
+        return "https://duckduckgo.com/?q=!\(percentEncode(bang))" + (rest.isEmpty ? "" : "+\(percentEncode(rest))")
     }
+
+    /// DuckDuckGo: no address bar suggestions (`kac=-1`) and no ads (`k1=-1`). Google: no results
+    /// personalized from account history (`pws=0`). The other engines have no such URL parameters.
+    static func searchPrivacyParameters(for engine: String) -> [String] {
+        switch engine {
+        case "ddg", "duckduckgo":
+            return ["kac=-1", "k1=-1"]
+        case "bing", "brave", "kagi", "startpage", "ecosia":
+            return []
+        default:
+            return ["pws=0"]
+        }
+    }
+
+    public func cleanURL(_ location: String) -> String {
+        removingURLParams(matching: cleanURLParams, from: location)
+    }
+}
+
+/// `location` without the query parameters whose names match `patterns` (see `matchesURLParamPattern`).
+func removingURLParams(matching patterns: [String], from location: String) -> String {
+    guard !patterns.isEmpty,
+          location.hasPrefix("http://") || location.hasPrefix("https://"),
+          let queryStart = location.firstIndex(of: "?") else {
+        return location
+    }
+
+    let base = location[..<queryStart]
+    var rest = location[location.index(after: queryStart)...]
+    var fragment = ""
+    if let hash = rest.firstIndex(of: "#") {
+        fragment = String(rest[hash...])
+        rest = rest[..<hash]
+    }
+
+    let kept = rest.split(separator: "&", omittingEmptySubsequences: true).filter { pair in
+        let name = percentDecode(String(pair.split(separator: "=", maxSplits: 1).first ?? "")).lowercased()
+        return !patterns.contains { matchesURLParamPattern(name, pattern: $0) }
+    }
+    guard !kept.isEmpty else {
+        return String(base) + fragment
+    }
+    return String(base) + "?" + kept.joined(separator: "&") + fragment
+}
+
+func matchesURLParamPattern(_ name: String, pattern: String) -> Bool {
+    let pattern = pattern.trimmingCharacters(in: .whitespacesAndNewlines).lowercased()
+    if pattern.hasSuffix("*") {
+        return name.hasPrefix(String(pattern.dropLast()))
+    }
+    return name == pattern
 }
 
 public struct CommandInfo {
@@ -107,22 +459,107 @@ public struct CommandInfo {
     public let example: String
     public let origin: String
     public let suggestURL: String?
+    /// Second words the command declares in `subcommands`, e.g. `reels` for `ig reels`.
+    public let subcommands: [String]
+    /// Optional `author`, `version` and `homepage` fields for tooling; they don't affect routing.
+    public let author: String?
+    public let version: String?
+    public let homepage: String?
+    /// Seconds a `process` result is reused for identical arguments, from `cacheable = true` and
+    /// `cache_ttl` (default 60). Nil when the command is not cacheable.
+    public let cacheTTL: TimeInterval?
+
+    func overriding(description: String? = nil, example: String? = nil) -> CommandInfo {
+        CommandInfo(
+            bindings: bindings,
+            description: description ?? self.description,
+            example: example ?? self.example,
+            origin: origin,
+            suggestURL: suggestURL,
+            subcommands: subcommands,
+            author: author,
+            version: version,
+            homepage: homepage,
+            cacheTTL: cacheTTL
+        )
+    }
+}
+
+/// Replaces what a command declares about itself in listings, keyed by any of its bindings in
+/// `AppConfig.commandMeta`.
+public struct CommandMetaOverride {
+    public var description: String?
+    public var example: String?
+
+    public init(description: String? = nil, example: String? = nil) {
+        self.description = description
+        self.example = example
+    }
+}
+
+/// A problem `lolabunny plugin lint` found in a command file. Only errors fail the lint.
+public struct CommandLintFinding {
+    public enum Severity: String {
+        case error
+        case warning
+    }
+
+    public let severity: Severity
+    public let path: String
+    public let message: String
 }
 
 final class CommandRegistry {
     private let commands: [LuaCommand]
-
-    init() {
-        commands = Self.discoverLuaCommandInfo().sorted {
-            ($0.info.bindings.first ?? "").localizedCaseInsensitiveCompare($1.info.bindings.first ?? "")
-                == .orderedAscending
+    private let transforms: [LuaURLTransform]
+    /// Why the plugin scan was cut short, if it was.
+    let scanWarnings: [String]
+    private let resultCache = LuaCommandResultCache(capacity: 256)
+
+    init(
+        loadingCommands: Bool = true,
+        scan: PluginScanConfig = PluginScanConfig(),
+        meta: [String: CommandMetaOverride] = [:]
+    ) {
+        guard loadingCommands else {
+            commands = []
+            transforms = []
+            scanWarnings = []
+            return
         }
+        let (files, warnings) = Self.luaSourceFiles(scan: scan)
+        scanWarnings = warnings
+        commands = Self.discoverLuaCommandInfo(in: files)
+            .map { $0.applying(meta) }
+            .sorted {
+                ($0.info.bindings.first ?? "").localizedCaseInsensitiveCompare($1.info.bindings.first ?? "")
+                    == .orderedAscending
+            }
+        transforms = Self.discoverLuaURLTransforms(in: files)
+    }
+
+    /// Runs every `transform(url)` function over `location`, in source path order.
+    func transform(_ location: String) -> String {
+        transforms.reduce(location) { $1.apply(to: $0) }
     }
 
     func allCommands() -> [CommandInfo] {
         commands.map(\.info)
     }
 
+    /// Like `allCommands()`, but runs each command's optional `example()` function for a current example.
+    func allCommandsWithLiveExamples() -> [CommandInfo] {
+        commands.map(\.infoWithLiveExample)
+    }
+
+    /// What each command's `process` returns for the argument `placeholder`.
+    func results(forPlaceholder placeholder: String) -> [(info: CommandInfo, result: LuaCommandResult?)] {
+        commands.map { command in
+            let binding = command.info.bindings.first ?? ""
+            return (command.info, command.execute("\(binding) \(placeholder)"))
+        }
+    }
+
     func commandInfo(for binding: String) -> CommandInfo? {
         command(for: binding)?.info
     }
@@ -137,30 +574,178 @@ final class CommandRegistry {
         commands.first { $0.shouldHandle(query) }
     }
 
-    private static func discoverLuaCommandInfo() -> [LuaCommand] {
+    /// For `origin:binding` queries such as `user:gh foo`, the command for `binding` from that origin
+    /// only, and the query without the origin prefix. Nil when no command matches both.
+    func originQualifiedCommand(in query: String) -> (command: LuaCommand, query: String)? {
+        let word = commandName(from: query)
+        guard let colon = word.firstIndex(of: ":") else {
+            return nil
+        }
+        let origin = String(word[..<colon])
+        let binding = String(word[word.index(after: colon)...])
+        guard !origin.isEmpty, !binding.isEmpty,
+              let command = commands.first(where: { command in
+                  command.info.origin.caseInsensitiveCompare(origin) == .orderedSame
+                      && command.info.bindings.contains { $0.caseInsensitiveCompare(binding) == .orderedSame }
+              }) else {
+            return nil
+        }
+        return (command, binding + query.dropFirst(word.count))
+    }
+
+    /// Common mistakes in the loaded command files, in load order.
+    func lint() -> [CommandLintFinding] {
+        var findings: [CommandLintFinding] = []
+        var declaredBy: [String: String] = [:]
+        for command in commands {
+            let path = command.sourceURL.path
+            func report(_ severity: CommandLintFinding.Severity, _ message: String) {
+                findings.append(CommandLintFinding(severity: severity, path: path, message: message))
+            }
+
+            if !command.hasFunction("process") {
+                report(.error, "`process` is not defined")
+            }
+            for binding in command.info.bindings {
+                if binding.contains(where: \.isWhitespace) {
+                    report(.error, "binding '\(binding)' contains whitespace")
+                }
+                if let other = declaredBy[binding.lowercased()], other != path {
+                    report(.error, "binding '\(binding)' is also declared by \(other)")
+                } else {
+                    declaredBy[binding.lowercased()] = path
+                }
+            }
+
+            if command.info.example.isEmpty {
+                if !command.hasFunction("example") {
+                    report(.warning, "no `example`")
+                }
+            } else {
+                let firstWord = commandName(from: command.info.example)
+                if !command.info.bindings.contains(where: { $0.caseInsensitiveCompare(firstWord) == .orderedSame }) {
+                    report(.warning, "example '\(command.info.example)' does not start with a declared binding")
+                }
+            }
+        }
+        return findings
+    }
+
+    /// Runs `command`, reusing a cached result for cacheable commands.
+    func execute(_ command: LuaCommand, query: String, parameters: [String: String], locale: String?) -> LuaCommandResult? {
+        guard let ttl = command.info.cacheTTL else {
+            return command.execute(query, parameters: parameters, locale: locale)
+        }
+        let key = ([command.sourceURL.path, query, locale ?? ""]
+            + parameters.sorted { $0.key < $1.key }.map { "\($0.key)=\($0.value)" })
+            .joined(separator: "\n")
+        if let cached = resultCache.value(for: key) {
+            return cached
+        }
+        let result = command.execute(query, parameters: parameters, locale: locale)
+        if let result {
+            resultCache.insert(result, for: key, ttl: ttl)
+        }
+        return result
+    }
+
+    /// Files whose table declares `transform = true`. Like `bindings`, the key is read from the source
+    /// without running it.
+    private static func discoverLuaURLTransforms(in files: [(url: URL, root: URL)]) -> [LuaURLTransform] {
+        files
+            .map(\.url)
+            .filter { url in
+                guard let source = luaSource(at: url) else {
+                    return false
+                }
+                return firstRegexCapture(pattern: #"\btransform\s*=\s*(true)\b"#, source: source) != nil
+            }
+            .sorted { $0.path < $1.path }
+            .map(LuaURLTransform.init(sourceURL:))
+    }
+
+    private static func discoverLuaCommandInfo(in files: [(url: URL, root: URL)]) -> [LuaCommand] {
+        files.compactMap { parseLuaCommandInfo(at: $0.url, root: $0.root) }
+    }
+
+    /// Lua files under the plugin directories, at most `scan.maxDepth` levels deep and
+    /// `scan.maxFiles` in total, so a directory pointed at a huge tree can't stall startup, plus a
+    /// warning for each limit that was hit. Each directory is sorted before the file limit applies, so
+    /// the same files survive whatever order the filesystem lists them in.
+    /// Symlinked directories are followed, but every real directory is scanned once: a link back up
+    /// the tree can't loop, and a package directory is only scanned as its own plugin directory.
+    private static func luaSourceFiles(scan: PluginScanConfig) -> (files: [(url: URL, root: URL)], warnings: [String]) {
         let fm = FileManager.default
-        var results: [LuaCommand] = []
-        for directory in Paths.pluginDirectories() {
-            guard let enumerator = fm.enumerator(
-                at: directory,
-                includingPropertiesForKeys: [.isRegularFileKey],
-                options: [.skipsHiddenFiles]
-            ) else {
-                continue
-            }
-            for case let url as URL in enumerator where url.pathExtension == "lua" {
-                guard let info = parseLuaCommandInfo(at: url, root: directory) else {
+        let directories = Paths.pluginDirectories()
+        var visited = Set(directories.map(canonicalPath))
+        var results: [(url: URL, root: URL)] = []
+        var warnings: [String] = []
+        var truncatedFiles = false
+        for directory in directories {
+            var files: [URL] = []
+            var truncatedDepth = false
+            var pending: [(url: URL, level: Int)] = [(directory, 0)]
+            while let next = pending.popLast() {
+                guard let enumerator = fm.enumerator(
+                    at: next.url,
+                    includingPropertiesForKeys: [.isRegularFileKey, .isDirectoryKey, .isSymbolicLinkKey],
+                    options: [.skipsHiddenFiles]
+                ) else {
                     continue
                 }
-                results.append(info)
+                while let url = enumerator.nextObject() as? URL {
+                    let values = try? url.resourceValues(forKeys: [.isDirectoryKey, .isSymbolicLinkKey])
+                    let isLinkedDirectory = values?.isSymbolicLink == true
+                        && (try? url.resolvingSymlinksInPath().resourceValues(forKeys: [.isDirectoryKey]))?.isDirectory == true
+                    if values?.isDirectory == true || isLinkedDirectory {
+                        let level = next.level + enumerator.level
+                        guard level < scan.maxDepth else {
+                            enumerator.skipDescendants()
+                            truncatedDepth = true
+                            continue
+                        }
+                        guard visited.insert(canonicalPath(url)).inserted else {
+                            enumerator.skipDescendants()
+                            continue
+                        }
+                        if isLinkedDirectory {
+                            pending.append((url, level))
+                        }
+                        continue
+                    }
+                    guard url.pathExtension == "lua" || url.lastPathComponent.hasSuffix(".lua.gz") else {
+                        continue
+                    }
+                    files.append(url)
+                }
+            }
+            if truncatedDepth {
+                warnings.append("skipped directories deeper than \(scan.maxDepth) levels in \(directory.path) (--plugins-max-depth)")
             }
+            let sorted = sortedSourceFiles(files, root: directory)
+            let room = max(0, scan.maxFiles - results.count)
+            truncatedFiles = truncatedFiles || sorted.count > room
+            results += sorted.prefix(room)
         }
-        return results
+        if truncatedFiles {
+            warnings.append("stopped scanning commands at \(scan.maxFiles) files (--plugins-max-files)")
+        }
+        return (results, warnings)
+    }
+
+    private static func canonicalPath(_ url: URL) -> String {
+        url.resolvingSymlinksInPath().standardizedFileURL.path
+    }
+
+    /// Enumeration follows filesystem order, which varies; sort so the first of two colliding files
+    /// always wins.
+    private static func sortedSourceFiles(_ files: [URL], root: URL) -> [(url: URL, root: URL)] {
+        files.sorted { $0.path < $1.path }.map { ($0, root) }
     }
 
     private static func parseLuaCommandInfo(at url: URL, root: URL) -> LuaCommand? {
-        guard let source = try? String(contentsOf: url, encoding: .utf8),
-              let bindings = parseBindings(from: source),
+        guard let source = luaSource(at: url),
+              let bindings = parseStringList("bindings", from: source),
               !bindings.isEmpty else {
             return nil
         }
@@ -169,6 +754,8 @@ final class CommandRegistry {
         let path = url.path
         if path.contains("/lola-core/") {
             origin = "lola-core"
+        } else if root.standardizedFileURL.path == Paths.systemCommandsDirectory.standardizedFileURL.path {
+            origin = "system"
         } else if root.lastPathComponent == "commands" || root.lastPathComponent == ".lolabunny" {
             origin = "user"
         } else {
@@ -180,14 +767,19 @@ final class CommandRegistry {
             description: parseStringField("description", from: source) ?? "",
             example: parseStringField("example", from: source) ?? "",
             origin: origin,
-            suggestURL: parseStringField("suggest_url", from: source)
+            suggestURL: parseStringField("suggest_url", from: source),
+            subcommands: parseStringList("subcommands", from: source) ?? [],
+            author: parseStringField("author", from: source),
+            version: parseStringField("version", from: source),
+            homepage: parseStringField("homepage", from: source),
+            cacheTTL: parseCacheTTL(from: source)
         )
         return LuaCommand(info: info, sourceURL: url)
     }
 
-    private static func parseBindings(from source: String) -> [String]? {
+    private static func parseStringList(_ field: String, from source: String) -> [String]? {
         guard let block = firstRegexCapture(
-            pattern: #"bindings\s*=\s*\{([^}]*)\}"#,
+            pattern: #"\b\#(field)\s*=\s*\{([^}]*)\}"#,
             source: source
         ) else {
             return nil
@@ -204,6 +796,14 @@ final class CommandRegistry {
         }
     }
 
+    private static func parseCacheTTL(from source: String) -> TimeInterval? {
+        guard firstRegexCapture(pattern: #"\bcacheable\s*=\s*(true)\b"#, source: source) != nil else {
+            return nil
+        }
+        return firstRegexCapture(pattern: #"\bcache_ttl\s*=\s*([0-9]+(?:\.[0-9]+)?)"#, source: source)
+            .flatMap(TimeInterval.init) ?? 60
+    }
+
     private static func parseStringField(_ field: String, from source: String) -> String? {
         firstRegexCapture(
             pattern: #"\#(field)\s*=\s*"([^"]*)""#,
@@ -228,9 +828,45 @@ final class CommandRegistry {
 struct LuaCommand {
     let info: CommandInfo
     let sourceURL: URL
+    /// Set when configuration overrides the example, so `example()` is not consulted.
+    var exampleOverridden = false
 
-    func execute(_ query: String) -> String? {
-        runLua(function: "process", query: query)?.nilIfEmpty
+    /// The command with the first override configured for one of its bindings applied.
+    func applying(_ meta: [String: CommandMetaOverride]) -> LuaCommand {
+        guard let override = info.bindings.lazy.compactMap({ meta[$0.lowercased()] }).first else {
+            return self
+        }
+        return LuaCommand(
+            info: info.overriding(description: override.description, example: override.example),
+            sourceURL: sourceURL,
+            exampleOverridden: override.example != nil
+        )
+    }
+
+    func execute(_ query: String, parameters: [String: String] = [:], locale: String? = nil) -> LuaCommandResult? {
+        let subcommand = subcommand(in: query)
+        return withRuntime {
+            try $0.process(query: query, parameters: parameters, subcommand: subcommand, locale: locale)
+        }
+    }
+
+    /// The declared subcommand named by the word after the binding, if any.
+    func subcommand(in query: String) -> String? {
+        let words = query.split(whereSeparator: { $0 == " " || $0 == "\t" })
+        guard words.count > 1 else {
+            return nil
+        }
+        let word = words[1].lowercased()
+        return info.subcommands.first { $0.lowercased() == word }
+    }
+
+    var infoWithLiveExample: CommandInfo {
+        guard !exampleOverridden,
+              hasFunction("example"),
+              let example = runLua(function: "example", query: "")?.nilIfEmpty else {
+            return info
+        }
+        return info.overriding(example: example)
     }
 
     func shouldHandle(_ query: String) -> Bool {
@@ -245,21 +881,29 @@ struct LuaCommand {
         return value == "true" || value == "1"
     }
 
-    private func hasFunction(_ name: String) -> Bool {
-        guard let source = try? String(contentsOf: sourceURL, encoding: .utf8) else {
+    func hasFunction(_ name: String) -> Bool {
+        guard let source = luaSource(at: sourceURL) else {
             return false
         }
-        return source.contains("function \(name)")
+        return luaSourceDefinesFunction(name, in: source)
     }
 
     private func runLua(function: String, query: String) -> String? {
-        guard let source = try? String(contentsOf: sourceURL, encoding: .utf8) else {
+        withRuntime { try $0.call(function: function, query: query) }
+    }
+
+    private func withRuntime<T>(_ body: (EmbeddedLuaCommandRuntime) throws -> T?) -> T? {
+        guard let source = luaSource(at: sourceURL) else {
             return nil
         }
 
         do {
-            return try EmbeddedLuaCommandRuntime(source: source, chunkName: sourceURL.path)
-                .call(function: function, query: query)
+            return try body(EmbeddedLuaCommandRuntime(
+                source: source,
+                chunkName: sourceURL.path,
+                storeNamespace: info.bindings.first?.lowercased(),
+                dataDirectory: sourceURL.deletingLastPathComponent()
+            ))
         } catch {
             fputs("Warning: Failed to run command \(sourceURL.path): \(error.localizedDescription)\n", stderr)
             return nil
@@ -267,10 +911,150 @@ struct LuaCommand {
     }
 }
 
+/// A Lua file defining `transform(url)`, which may rewrite any resolved location. Returning nil
+/// leaves the location unchanged.
+struct LuaURLTransform {
+    let sourceURL: URL
+
+    func apply(to location: String) -> String {
+        guard let source = luaSource(at: sourceURL) else {
+            return location
+        }
+
+        do {
+            return try EmbeddedLuaCommandRuntime(
+                source: source,
+                chunkName: sourceURL.path,
+                dataDirectory: sourceURL.deletingLastPathComponent()
+            )
+                .call(function: "transform", query: location)?
+                .nilIfEmpty ?? location
+        } catch {
+            fputs("Warning: Failed to run transform \(sourceURL.path): \(error.localizedDescription)\n", stderr)
+            return location
+        }
+    }
+}
+
+/// Bounded cache of `process` results for cacheable commands, evicting the least recently used.
+final class LuaCommandResultCache: @unchecked Sendable {
+    private let lock = NSLock()
+    private let capacity: Int
+    private var entries: [String: (result: LuaCommandResult, expires: Date, lastUsed: UInt64)] = [:]
+    private var clock: UInt64 = 0
+
+    init(capacity: Int) {
+        self.capacity = capacity
+    }
+
+    func value(for key: String) -> LuaCommandResult? {
+        lock.lock()
+        defer { lock.unlock() }
+        guard let entry = entries[key] else {
+            return nil
+        }
+        guard entry.expires > Date() else {
+            entries[key] = nil
+            return nil
+        }
+        clock += 1
+        entries[key]?.lastUsed = clock
+        return entry.result
+    }
+
+    func insert(_ result: LuaCommandResult, for key: String, ttl: TimeInterval) {
+        lock.lock()
+        defer { lock.unlock() }
+        clock += 1
+        entries[key] = (result, Date().addingTimeInterval(ttl), clock)
+        if entries.count > capacity,
+           let oldest = entries.min(by: { $0.value.lastUsed < $1.value.lastUsed })?.key {
+            entries[oldest] = nil
+        }
+    }
+}
+
+/// Small per-command string store behind `kv_get`/`kv_set`, kept as JSON in `Paths.keyValueStoreFile`.
+///
+/// Best-effort: every call rereads and rewrites the whole file under a lock shared with other lolabunny
+/// processes, and failures to read or write are reported as warnings and otherwise ignored.
+final class LuaKeyValueStore: @unchecked Sendable {
+    static let shared = LuaKeyValueStore()
+
+    private let lock = NSLock()
+
+    func value(for key: String, in namespace: String) -> String? {
+        withLockedFile { $0[namespace]?[key] }
+    }
+
+    func set(_ value: String?, for key: String, in namespace: String) {
+        withLockedFile { (values: inout [String: [String: String]]) in
+            values[namespace, default: [:]][key] = value
+            if values[namespace]?.isEmpty == true {
+                values[namespace] = nil
+            }
+        }
+    }
+
+    private func withLockedFile<T>(_ body: (inout [String: [String: String]]) -> T) -> T {
+        lock.lock()
+        defer { lock.unlock() }
+
+        let file = Paths.keyValueStoreFile
+        try? FileManager.default.createDirectory(at: file.deletingLastPathComponent(), withIntermediateDirectories: true)
+        let lockFD = open(file.path + ".lock", O_CREAT | O_RDWR, 0o600)
+        if lockFD >= 0 {
+            flock(lockFD, LOCK_EX)
+        }
+        defer {
+            if lockFD >= 0 {
+                flock(lockFD, LOCK_UN)
+                close(lockFD)
+            }
+        }
+
+        var values: [String: [String: String]] = [:]
+        if let data = try? Data(contentsOf: file), !data.isEmpty {
+            if let decoded = try? JSONDecoder().decode([String: [String: String]].self, from: data) {
+                values = decoded
+            } else {
+                fputs("Warning: Ignoring unreadable command store \(file.path)\n", stderr)
+            }
+        }
+        let original = values
+        let result = body(&values)
+        if values != original {
+            do {
+                try JSONEncoder().encode(values).write(to: file, options: .atomic)
+            } catch {
+                fputs("Warning: Failed to write command store \(file.path): \(error.localizedDescription)\n", stderr)
+            }
+        }
+        return result
+    }
+}
+
+/// What a command's `process` function asked for.
+///
+/// `process` may return a URL string, nil to let the next command or the default search handle the
+/// query, or a table. `{ fallback = true, engine = "ddg", query = "..." }` explicitly defers to a
+/// search engine; `engine` and `query` are optional. `{ error = "usage: gh owner/repo" }` reports a
+/// validation error to the user instead of producing a broken URL. An array of URL strings, or of
+/// `{ label = "...", url = "..." }` tables, offers all of them.
+enum LuaCommandResult {
+    case url(String)
+    /// `labels` has one entry per URL; unlabeled URLs are their own label.
+    case urls([String], labels: [String])
+    case fallback(engine: String?, query: String?)
+    case error(String)
+}
+
 private final class EmbeddedLuaCommandRuntime {
     private let engine: LuaEngine
 
-    init(source: String, chunkName: String) throws {
+    /// `storeNamespace` enables `kv_get`/`kv_set`, keeping the command's values apart from other commands'.
+    /// `dataDirectory` is where `read_data` finds files, normally the directory holding the source.
+    init(source: String, chunkName: String, storeNamespace: String? = nil, dataDirectory: URL? = nil) throws {
         let configuration = LuaEngineConfiguration(
             sandboxed: true,
             vmMemoryLimit: 8 * 1_024 * 1_024
@@ -278,6 +1062,17 @@ private final class EmbeddedLuaCommandRuntime {
         engine = try LuaEngine(configuration: configuration)
         engine.setInstructionLimit(250_000)
         registerHelpers()
+        engine.registerFunction(name: "_read_data") { values in
+            dataDirectory.flatMap { dataFileContents(luaStringArgument(values), in: $0) }.map(LuaValue.string) ?? .nil
+        }
+        if let storeNamespace {
+            registerStoreHelpers(namespace: storeNamespace)
+        }
+        try engine.run(Self.luaHelpers, chunkName: "helpers")
+        let prelude = Paths.preludeFile
+        if let preludeSource = try? String(contentsOf: prelude, encoding: .utf8) {
+            try engine.run(preludeSource, chunkName: prelude.path)
+        }
         try engine.run(source, chunkName: chunkName)
     }
 
@@ -301,20 +1096,146 @@ private final class EmbeddedLuaCommandRuntime {
         }
     }
 
+    /// `locale` overrides what `locale()` returns, which is otherwise taken from the environment.
+    func process(
+        query: String,
+        parameters: [String: String],
+        subcommand: String?,
+        locale: String? = nil
+    ) throws -> LuaCommandResult? {
+        if let locale {
+            engine.registerFunction(name: "locale") { _ in .string(locale) }
+        }
+        let result = try engine.evaluate("""
+        local fn = _G.process
+        if type(fn) ~= "function" then return nil end
+        local result = fn(\(luaStringLiteral(query)), \(luaTableLiteral(parameters)), \(subcommand.map(luaStringLiteral) ?? "nil"))
+        if type(result) ~= "table" then return result end
+        if #result > 0 then
+          local urls = { "urls" }
+          for _, value in ipairs(result) do
+            if type(value) == "table" then
+              urls[#urls + 1] = tostring(value.url or "")
+              urls[#urls + 1] = tostring(value.label or "")
+            else
+              urls[#urls + 1] = tostring(value)
+              urls[#urls + 1] = ""
+            end
+          end
+          return urls
+        end
+        local fields = { "fields" }
+        for key, value in pairs(result) do
+          if type(key) == "string" then
+            fields[#fields + 1] = key
+            fields[#fields + 1] = tostring(value)
+          end
+        end
+        return fields
+        """)
+        switch result {
+        case .string(let value):
+            let url = value.trimmingCharacters(in: .whitespacesAndNewlines)
+            return url.isEmpty ? nil : .url(url)
+        case .array(let values) where luaStringArgument(values) == "fields":
+            var fields: [String: String] = [:]
+            var index = 1
+            while index + 1 < values.count {
+                fields[luaStringArgument(values, at: index)] = luaStringArgument(values, at: index + 1)
+                index += 2
+            }
+            return Self.result(from: fields)
+        case .array(let values) where luaStringArgument(values) == "urls":
+            let links = stride(from: 1, to: values.count - 1, by: 2).compactMap { index -> (url: String, label: String)? in
+                let url = luaStringArgument(values, at: index).trimmingCharacters(in: .whitespacesAndNewlines)
+                guard !url.isEmpty else {
+                    return nil
+                }
+                let label = luaStringArgument(values, at: index + 1).trimmingCharacters(in: .whitespacesAndNewlines)
+                return (url, label.isEmpty ? url : label)
+            }
+            guard let first = links.first else {
+                return nil
+            }
+            return links.count == 1 ? .url(first.url) : .urls(links.map(\.url), labels: links.map(\.label))
+        default:
+            return nil
+        }
+    }
+
+    private static func result(from fields: [String: String]) -> LuaCommandResult? {
+        if let message = fields["error"]?.nilIfEmpty {
+            return .error(message)
+        }
+        if fields["fallback"] == "true" {
+            return .fallback(engine: fields["engine"]?.nilIfEmpty, query: fields["query"]?.nilIfEmpty)
+        }
+        return fields["url"]?.nilIfEmpty.map(LuaCommandResult.url)
+    }
+
+    /// Helpers written in Lua so they can raise errors in the calling command, or return integers
+    /// rather than floats.
+    private static let luaHelpers = """
+    function arg_count(args)
+      return tonumber(_arg_count(args))
+    end
+
+    function url_decode(value)
+      local decoded = _url_decode(value)
+      if decoded == nil then
+        error("url_decode: invalid percent-encoding: " .. tostring(value), 2)
+      end
+      return decoded
+    end
+
+    function encode_for(value, context)
+      local encoded = _encode_for(value, context)
+      if encoded == nil then
+        error("encode_for: unknown context " .. tostring(context) .. " (expected query, path or fragment)", 2)
+      end
+      return encoded
+    end
+
+    function read_data(path)
+      local contents = _read_data(path)
+      if contents == nil then
+        error("read_data: cannot read " .. tostring(path) .. " from the command's directory", 2)
+      end
+      return contents
+    end
+    """
+
     private func registerHelpers() {
+        engine.registerFunction(name: "locale") { _ in
+            environmentLocale().map(LuaValue.string) ?? .nil
+        }
         engine.registerFunction(name: "url_encode") { values in
-            .string(percentEncode(luaStringArgument(values)))
+            .string(percentEncode(luaStringArgument(values), for: .query))
+        }
+        engine.registerFunction(name: "_encode_for") { values in
+            guard let context = URLEncodingContext(rawValue: luaStringArgument(values, at: 1)) else {
+                return .nil
+            }
+            return .string(percentEncode(luaStringArgument(values, at: 0), for: context))
+        }
+        engine.registerFunction(name: "_url_decode") { values in
+            luaStringArgument(values).removingPercentEncoding.map(LuaValue.string) ?? .nil
         }
         engine.registerFunction(name: "url_encode_path") { values in
-            .string(percentEncode(luaStringArgument(values), allowingSlash: true))
+            .string(percentEncode(luaStringArgument(values), for: .path))
         }
         engine.registerFunction(name: "get_args") { values in
-            let fullArgs = luaStringArgument(values, at: 0)
-            let binding = luaStringArgument(values, at: 1)
-            if fullArgs.hasPrefix(binding) {
-                return .string(String(fullArgs.dropFirst(binding.count)).trimmingCharacters(in: .whitespacesAndNewlines))
+            .string(wholeWordArguments(after: luaStringArgument(values, at: 1), in: luaStringArgument(values, at: 0)))
+        }
+        engine.registerFunction(name: "_arg_count") { values in
+            .string(String(luaStringArgument(values).split(whereSeparator: \.isWhitespace).count))
+        }
+        engine.registerFunction(name: "arg_at") { values in
+            let words = luaStringArgument(values, at: 0).split(whereSeparator: \.isWhitespace)
+            guard let position = Int(luaStringArgument(values, at: 1)), words.indices.contains(position - 1) else {
+                return .nil
             }
-            return .string(fullArgs.trimmingCharacters(in: .whitespacesAndNewlines))
+            return .string(String(words[position - 1]))
         }
         engine.registerFunction(name: "trim") { values in
             .string(luaStringArgument(values).trimmingCharacters(in: .whitespacesAndNewlines))
@@ -342,6 +1263,99 @@ private final class EmbeddedLuaCommandRuntime {
             }
             return .array(value.components(separatedBy: separator).map(LuaValue.string))
         }
+        engine.registerFunction(name: "split_n") { values in
+            let value = luaStringArgument(values, at: 0)
+            let separator = luaStringArgument(values, at: 1)
+            let limit = Int(luaStringArgument(values, at: 2)) ?? 0
+            guard !separator.isEmpty, limit > 1 else {
+                return .array([.string(value)])
+            }
+            var parts: [String] = []
+            var remainder = Substring(value)
+            while parts.count < limit - 1, let range = remainder.range(of: separator) {
+                parts.append(String(remainder[..<range.lowerBound]))
+                remainder = remainder[range.upperBound...]
+            }
+            parts.append(String(remainder))
+            return .array(parts.map(LuaValue.string))
+        }
+    }
+
+    private func registerStoreHelpers(namespace: String) {
+        engine.registerFunction(name: "kv_get") { values in
+            LuaKeyValueStore.shared.value(for: luaStringArgument(values), in: namespace).map(LuaValue.string) ?? .nil
+        }
+        engine.registerFunction(name: "kv_set") { values in
+            let value: String?
+            switch values.count > 1 ? values[1] : .nil {
+            case .nil:
+                value = nil
+            default:
+                value = luaStringArgument(values, at: 1)
+            }
+            LuaKeyValueStore.shared.set(value, for: luaStringArgument(values, at: 0), in: namespace)
+            return .nil
+        }
+    }
+}
+
+public struct CommandRoute {
+    public let location: String
+    /// Primary binding and origin of the command that handled the query; nil when it fell back to a search.
+    public let binding: String?
+    public let origin: String?
+    /// File the handling command was loaded from; nil for built-in commands and searches.
+    public let sourcePath: String?
+    /// Validation error reported by the command; `location` is then a `data:` URL with the message.
+    public let error: String?
+    /// Every location when the command asked to open several; `location` is the first.
+    public let locations: [String]
+    /// What to call each of `locations` when offering a choice; the URL itself unless the command labeled it.
+    public let labels: [String]
+
+    init(
+        location: String,
+        binding: String?,
+        origin: String?,
+        sourcePath: String? = nil,
+        error: String? = nil,
+        locations: [String]? = nil,
+        labels: [String]? = nil
+    ) {
+        self.location = location
+        self.binding = binding
+        self.origin = origin
+        self.sourcePath = sourcePath
+        self.error = error
+        self.locations = locations ?? [location]
+        self.labels = labels ?? self.locations
+    }
+
+    public var matched: Bool {
+        binding != nil
+    }
+
+    /// No command handled the query and it was shorter than `AppConfig.minSearchLength`, so it goes
+    /// nowhere; `location` is empty.
+    public var searchSkipped: Bool {
+        !matched && locations.isEmpty
+    }
+
+    static func search(_ location: String) -> CommandRoute {
+        CommandRoute(location: location, binding: nil, origin: nil)
+    }
+
+    static var skippedSearch: CommandRoute {
+        CommandRoute(location: "", binding: nil, origin: nil, locations: [])
+    }
+
+    static func builtin(_ binding: String, location: String) -> CommandRoute {
+        CommandRoute(location: location, binding: binding.lowercased(), origin: "builtin")
+    }
+
+    /// The command that would handle a query, when it is not run; `locations` is empty.
+    static func unresolved(binding: String?, origin: String, sourcePath: String?) -> CommandRoute {
+        CommandRoute(location: "", binding: binding, origin: origin, sourcePath: sourcePath, locations: [])
     }
 }
 
@@ -352,6 +1366,11 @@ public final class CommandRouter {
         self.init(registry: CommandRegistry())
     }
 
+    /// A router for `config`. In safe mode no commands are loaded, so every query falls back to search.
+    public convenience init(config: AppConfig) {
+        self.init(registry: CommandRegistry(loadingCommands: !config.safeMode, scan: config.pluginScan, meta: config.commandMeta))
+    }
+
     init(registry: CommandRegistry) {
         self.registry = registry
     }
@@ -360,25 +1379,221 @@ public final class CommandRouter {
         registry.allCommands()
     }
 
-    public func route(_ rawQuery: String, config: AppConfig) -> String {
-        let resolvedQuery = config.resolveCommand(rawQuery)
+    public func allCommandsWithLiveExamples() -> [CommandInfo] {
+        registry.allCommandsWithLiveExamples()
+    }
+
+    /// The URL each command produces for the argument `{}`, as a map of where commands go. Commands
+    /// that produce no URL for it get nil; fallbacks and validation errors are described in parentheses.
+    public func urlTemplates() -> [(command: CommandInfo, template: String?)] {
+        let placeholder = "{}"
+        let encoded = [percentEncode(placeholder), percentEncode(placeholder).lowercased()]
+        func template(_ url: String) -> String {
+            encoded.reduce(url) { $0.replacingOccurrences(of: $1, with: placeholder) }
+        }
+        return registry.results(forPlaceholder: placeholder).map { info, result in
+            switch result {
+            case .url(let url):
+                return (info, template(url))
+            case .urls(let urls, _):
+                return (info, urls.map(template).joined(separator: " "))
+            case .fallback:
+                return (info, "(search)")
+            case .error(let message):
+                return (info, "(error: \(message))")
+            case nil:
+                return (info, nil)
+            }
+        }
+    }
+
+    public func lintCommands() -> [CommandLintFinding] {
+        registry.lint()
+    }
+
+    /// Limits the plugin scan hit while loading commands. Printed by `serve` and `doctor` only, so
+    /// other commands stay quiet about them.
+    public func pluginScanWarnings() -> [String] {
+        registry.scanWarnings
+    }
+
+    /// Loads a command's Lua `source` in isolation and runs its `process` for `query`, to try a command
+    /// out without installing it. Throws when the source fails to load or run.
+    public static func resolveSource(_ source: String, query: String, config: AppConfig = AppConfig()) throws -> CommandRoute {
+        let runtime = try EmbeddedLuaCommandRuntime(source: source, chunkName: "stdin")
+        switch try runtime.process(query: query, parameters: [:], subcommand: nil) {
+        case .url(let url):
+            return CommandRoute(location: url, binding: commandName(from: query), origin: "stdin")
+        case .urls(let urls, let labels):
+            return CommandRoute(
+                location: urls[0],
+                binding: commandName(from: query),
+                origin: "stdin",
+                locations: urls,
+                labels: labels
+            )
+        case .fallback(let engine, let fallbackQuery):
+            return .search(config.searchURL(for: fallbackQuery ?? query, engine: engine))
+        case .error(let message):
+            return CommandRoute(location: dataTextURL(text: message), binding: commandName(from: query), origin: "stdin", error: message)
+        case nil:
+            return .search(config.searchURL(for: query))
+        }
+    }
+
+    /// Aliases are resolved before commands, so an alias named like a command binding always wins.
+    /// Pass `query` to only report the alias that query would hit.
+    public func aliasShadowWarnings(config: AppConfig, query: String? = nil) -> [String] {
+        config.aliases.keys.sorted().compactMap { alias in
+            if let query, alias != commandName(from: query) {
+                return nil
+            }
+            guard let command = registry.commandInfo(for: alias) else {
+                return nil
+            }
+            return "alias '\(alias)' shadows the '\(alias)' command from \(command.origin); "
+                + "the alias wins because aliases are resolved before commands"
+        }
+    }
+
+    /// Routes `rawQuery` to a location. `parameters` are extra request parameters passed to the
+    /// command's `process` function as its second argument.
+    public func route(
+        _ rawQuery: String,
+        config: AppConfig,
+        parameters: [String: String] = [:],
+        locale: String? = nil
+    ) -> String {
+        resolveRoute(rawQuery, config: config, parameters: parameters, locale: locale).location
+    }
+
+    /// Like `route`, but also reports which command handled the query.
+    /// `locale` is the client's preferred language for the `locale()` helper; nil uses the environment.
+    public func resolveRoute(
+        _ rawQuery: String,
+        config: AppConfig,
+        parameters: [String: String] = [:],
+        locale: String? = nil
+    ) -> CommandRoute {
+        finishing(resolve(rawQuery, config: config, parameters: parameters, locale: locale), config: config)
+    }
+
+    /// Where `rawQuery` would go, without running any command's `process` or built-in: the command that
+    /// would handle it, with empty `locations`, or else the search it falls back to, exactly as
+    /// `resolveRoute` would send it. `should_handle` functions still run for queries no binding claims.
+    /// A command whose `process` returns nil would still pass the query on to search.
+    public func which(_ rawQuery: String, config: AppConfig) -> CommandRoute {
+        finishing(resolve(rawQuery, config: config, parameters: [:], locale: nil, execute: false), config: config)
+    }
+
+    /// `route` with its locations transformed and cleaned, as they are sent to the browser.
+    private func finishing(_ route: CommandRoute, config: AppConfig) -> CommandRoute {
+        if route.error != nil || route.locations.isEmpty {
+            return route
+        }
+        let locations = route.locations.map { config.cleanURL(registry.transform($0)) }
+        // An unlabeled location is named by its URL, which should be the one actually opened.
+        let labels = zip(route.labels, zip(route.locations, locations)).map { label, location in
+            label == location.0 ? location.1 : label
+        }
+        return CommandRoute(
+            location: locations[0],
+            binding: route.binding,
+            origin: route.origin,
+            sourcePath: route.sourcePath,
+            locations: locations,
+            labels: labels
+        )
+    }
+
+    /// With `execute` false, stops at the command that would handle the query instead of running it.
+    private func resolve(
+        _ rawQuery: String,
+        config: AppConfig,
+        parameters: [String: String],
+        locale: String?,
+        execute: Bool = true
+    ) -> CommandRoute {
+        guard let commandQuery = config.commandQuery(from: rawQuery) else {
+            return unmatchedSearch(rawQuery, config: config)
+        }
+        let resolvedQuery = config.applyingDefaultArguments(to: config.resolveCommand(commandQuery))
         let binding = commandName(from: resolvedQuery)
+        func handle(_ command: LuaCommand, query: String) -> CommandRoute? {
+            guard execute else {
+                return .unresolved(
+                    binding: command.info.bindings.first,
+                    origin: command.info.origin,
+                    sourcePath: command.sourceURL.path
+                )
+            }
+            return registry.execute(command, query: query, parameters: parameters, locale: locale)
+                .map { route(for: $0, from: command, query: query, config: config) }
+        }
+
+        if let qualified = registry.originQualifiedCommand(in: resolvedQuery),
+           let route = handle(qualified.command, query: config.applyingDefaultArguments(to: qualified.query)) {
+            return route
+        }
+        if !execute, ["lower", "giff", "m"].contains(binding.lowercased()) {
+            return .unresolved(binding: binding.lowercased(), origin: "builtin", sourcePath: nil)
+        }
 
         switch binding.lowercased() {
         case "lower":
-            return dataTextURL(text: arguments(after: binding, in: resolvedQuery).lowercased())
+            return .builtin(binding, location: dataTextURL(text: arguments(after: binding, in: resolvedQuery).lowercased()))
         case "giff", "m":
-            return giphyMarkdownURL(for: arguments(after: binding, in: resolvedQuery))
+            return .builtin(binding, location: giphyMarkdownURL(for: arguments(after: binding, in: resolvedQuery)))
         default:
-            if let command = registry.command(for: binding),
-               let url = command.execute(resolvedQuery) {
-                return url
+            if let command = registry.command(for: binding), let route = handle(command, query: resolvedQuery) {
+                return route
             }
             if let command = registry.commandThatShouldHandle(resolvedQuery),
-               let url = command.execute(resolvedQuery) {
-                return url
+               let route = handle(command, query: resolvedQuery) {
+                return route
             }
-            return config.searchURL(for: resolvedQuery)
+            return unmatchedSearch(resolvedQuery, config: config)
+        }
+    }
+
+    private func unmatchedSearch(_ query: String, config: AppConfig) -> CommandRoute {
+        if config.ddgBangs, let bang = config.bangSearchURL(for: query) {
+            return .search(bang)
+        }
+        guard query.trimmingCharacters(in: .whitespacesAndNewlines).count >= config.minSearchLength else {
+            return .skippedSearch
+        }
+        return .search(config.searchURL(for: query))
+    }
+
+    private func route(for result: LuaCommandResult, from command: LuaCommand, query: String, config: AppConfig) -> CommandRoute {
+        switch result {
+        case .url(let url):
+            return CommandRoute(
+                location: url,
+                binding: command.info.bindings.first,
+                origin: command.info.origin,
+                sourcePath: command.sourceURL.path
+            )
+        case .urls(let urls, let labels):
+            return CommandRoute(
+                location: urls[0],
+                binding: command.info.bindings.first,
+                origin: command.info.origin,
+                sourcePath: command.sourceURL.path,
+                locations: urls,
+                labels: labels
+            )
+        case .fallback(let engine, let fallbackQuery):
+            return .search(config.searchURL(for: fallbackQuery ?? query, engine: engine))
+        case .error(let message):
+            return CommandRoute(
+                location: dataTextURL(text: message),
+                binding: command.info.bindings.first,
+                origin: command.info.origin,
+                sourcePath: command.sourceURL.path,
+                error: message
+            )
         }
     }
 
@@ -421,17 +1636,77 @@ public final class CommandRouter {
     }
 }
 
-public final class History {
+/// One history line: `timestamp|user|command`, followed by a tab and the resolved URL when
+/// `history.storeURL` is enabled.
+public struct HistoryEntry {
+    public let timestamp: Int
+    public let user: String
+    public let command: String
+    public let url: String?
+
+    public init(timestamp: Int, user: String, command: String, url: String? = nil) {
+        self.timestamp = timestamp
+        self.user = user
+        self.command = command
+        self.url = url
+    }
+
+    init?(line: String) {
+        let parts = line.split(separator: "|", maxSplits: 2, omittingEmptySubsequences: false)
+        guard parts.count == 3, let timestamp = Int(parts[0]) else {
+            return nil
+        }
+        let commandAndURL = parts[2].split(separator: "\t", maxSplits: 1, omittingEmptySubsequences: false)
+        self.timestamp = timestamp
+        user = String(parts[1])
+        command = String(commandAndURL[0])
+        url = commandAndURL.count > 1 ? String(commandAndURL[1]).nilIfEmpty : nil
+    }
+
+    var line: String {
+        let base = "\(timestamp)|\(user)|\(command)"
+        guard let url else {
+            return base
+        }
+        return base + "\t" + url
+    }
+}
+
+public final class History: Sendable {
     private let path: URL
     private let maxEntries: Int
+    private let dedup: Bool
+    private let storeURL: Bool
+    private let encryptionKeyEnv: String?
+
+    /// Encrypted lines are `enc1:<salt>:<sealed box>`, both base64. The salt is the same for every line
+    /// of a file: new entries are appended under it, and it only changes when the file is rewritten to
+    /// drop entries.
+    private static let encryptedLinePrefix = "enc1:"
+    private static let keyDerivationRounds: UInt32 = 200_000
+    /// Keys derived so far in this process, so PBKDF2 runs once per salt rather than once per entry.
+    private static let derivedKeys = HistoryKeyCache()
+    /// Serial, so background writes land in the order they were queued.
+    private static let writeQueue = DispatchQueue(label: "lolabunny.history.writes", qos: .utility)
 
     public init(config: AppConfig) {
         path = Paths.historyFile
         maxEntries = config.history.maxEntries
+        dedup = config.history.dedup
+        storeURL = config.history.storeURL
+        encryptionKeyEnv = config.history.encryptionKeyEnv?.nilIfEmpty
     }
 
-    public func add(command: String, user: String) {
-        let trimmed = command.trimmingCharacters(in: .whitespacesAndNewlines)
+    /// Recorded entries, oldest first. Throws when encrypted entries can't be decrypted.
+    public func entries() throws -> [HistoryEntry] {
+        try readLines().compactMap(HistoryEntry.init(line:))
+    }
+
+    /// Records `command`. `url` is the resolved location and is only stored when `history.storeURL` is on.
+    public func add(command: String, user: String, url: String? = nil) {
+        let trimmed = command
+            .replacingOccurrences(of: "\t", with: " ")
+            .trimmingCharacters(in: .whitespacesAndNewlines)
         guard !trimmed.isEmpty else {
             return
         }
@@ -441,18 +1716,218 @@ public final class History {
                 at: path.deletingLastPathComponent(),
                 withIntermediateDirectories: true
             )
-            var lines = ((try? String(contentsOf: path, encoding: .utf8)) ?? "")
-                .split(separator: "\n", omittingEmptySubsequences: true)
-                .map(String.init)
-            lines.append("\(Int(Date().timeIntervalSince1970))|\(user)|\(trimmed)")
-            if lines.count > maxEntries {
-                lines = Array(lines.suffix(maxEntries))
-            }
-            try (lines.joined(separator: "\n") + "\n").write(to: path, atomically: true, encoding: .utf8)
+            let entry = HistoryEntry(
+                timestamp: Int(Date().timeIntervalSince1970),
+                user: user,
+                command: trimmed,
+                url: storeURL ? url.map(Self.storableURL) : nil
+            )
+            let stored = storedLines()
+            if try !appendIfPossible(entry, to: stored) {
+                try rewrite(adding: entry, to: stored)
+            }
         } catch {
             fputs("Warning: Failed to save history: \(error.localizedDescription)\n", stderr)
         }
     }
+
+    /// Like `add`, but returns at once and writes on a background queue.
+    public func addInBackground(command: String, user: String, url: String? = nil) {
+        Self.writeQueue.async {
+            self.add(command: command, user: user, url: url)
+        }
+    }
+
+    /// `url` as recorded: without whitespace, and without parameters such as Kagi's `token` that carry
+    /// credentials.
+    private static func storableURL(_ url: String) -> String {
+        removingURLParams(matching: ["token"], from: url.components(separatedBy: .whitespacesAndNewlines).joined())
+    }
+
+    /// Waits for writes queued by `addInBackground`.
+    public static func flushPendingWrites() {
+        writeQueue.sync {}
+    }
+
+    private func readLines() throws -> [String] {
+        try storedLines().map(decode)
+    }
+
+    /// Lines as they are on disk, still encrypted.
+    private func storedLines() -> [String] {
+        ((try? String(contentsOf: path, encoding: .utf8)) ?? "")
+            .split(separator: "\n", omittingEmptySubsequences: true)
+            .map(String.init)
+    }
+
+    /// Appends `entry` without touching existing lines. Returns false when the file has to be
+    /// rewritten instead: to drop a duplicate or the oldest entry, or because the existing lines are
+    /// not all stored the way new ones would be (plaintext, or encrypted under one salt).
+    private func appendIfPossible(_ entry: HistoryEntry, to stored: [String]) throws -> Bool {
+        guard stored.count < maxEntries else {
+            return false
+        }
+        let salts = Set(stored.map(Self.salt(of:)))
+        let key: (salt: Data, key: SymmetricKey)?
+        if try passphrase() == nil {
+            guard salts.isSubset(of: [nil]) else {
+                return false
+            }
+            key = nil
+        } else if let only = salts.first, salts.count == 1 {
+            guard let salt = only.flatMap({ Data(base64Encoded: $0) }) else {
+                return false
+            }
+            key = try encryptionKey(salt: salt)
+        } else if salts.isEmpty {
+            key = try newEncryptionKey()
+        } else {
+            return false
+        }
+        // Decrypting refuses a wrong key, rather than appending entries that can't be read back.
+        if dedup {
+            guard try !stored.contains(where: { try HistoryEntry(line: decode($0))?.command == entry.command }) else {
+                return false
+            }
+        } else if let last = stored.last {
+            _ = try decode(last)
+        }
+
+        let line = try encode(entry.line, with: key) + "\n"
+        guard let handle = try? FileHandle(forWritingTo: path) else {
+            try line.write(to: path, atomically: true, encoding: .utf8)
+            return true
+        }
+        defer { try? handle.close() }
+        try handle.seekToEnd()
+        try handle.write(contentsOf: Data(line.utf8))
+        return true
+    }
+
+    /// Rewrites the whole file with `entry` added, under a fresh salt.
+    private func rewrite(adding entry: HistoryEntry, to stored: [String]) throws {
+        // Refuses to write when existing entries can't be decrypted, rather than dropping them.
+        var lines = try stored.map(decode)
+        if dedup {
+            lines.removeAll { HistoryEntry(line: $0)?.command == entry.command }
+        }
+        lines.append(entry.line)
+        if lines.count > maxEntries {
+            lines = Array(lines.suffix(maxEntries))
+        }
+        let key = try newEncryptionKey()
+        let encoded = try lines.map { try encode($0, with: key) }
+        try (encoded.joined(separator: "\n") + "\n").write(to: path, atomically: true, encoding: .utf8)
+    }
+
+    /// The base64 salt of an encrypted line, or nil for a plaintext one.
+    private static func salt(of stored: String) -> String? {
+        guard stored.hasPrefix(encryptedLinePrefix) else {
+            return nil
+        }
+        return stored.dropFirst(encryptedLinePrefix.count).split(separator: ":", maxSplits: 1).first.map(String.init)
+    }
+
+    /// The passphrase in `encryptionKeyEnv`, or nil when encryption is off.
+    private func passphrase() throws -> String? {
+        guard let encryptionKeyEnv else {
+            return nil
+        }
+        guard let passphrase = ProcessInfo.processInfo.environment[encryptionKeyEnv]?.nilIfEmpty else {
+            throw ServerError.message("history encryption key variable \(encryptionKeyEnv) is not set")
+        }
+        return passphrase
+    }
+
+    /// A key for rewriting the file under a fresh random salt, or nil when encryption is off.
+    private func newEncryptionKey() throws -> (salt: Data, key: SymmetricKey)? {
+        try encryptionKey(salt: SymmetricKey(size: .bits128).withUnsafeBytes { Data($0) })
+    }
+
+    /// The key for `salt`, derived at most once per process. Nil when encryption is off.
+    private func encryptionKey(salt: Data) throws -> (salt: Data, key: SymmetricKey)? {
+        guard let passphrase = try passphrase() else {
+            return nil
+        }
+        let key = try Self.derivedKeys.key(passphrase: passphrase, salt: salt) {
+            try Self.derivedKey(passphrase: passphrase, salt: salt)
+        }
+        return (salt, key)
+    }
+
+    /// Stretches `passphrase` into a 256-bit key with PBKDF2-HMAC-SHA256 over `salt`.
+    private static func derivedKey(passphrase: String, salt: Data) throws -> SymmetricKey {
+        var key = [UInt8](repeating: 0, count: 32)
+        let status = salt.withUnsafeBytes { saltBytes in
+            CCKeyDerivationPBKDF(
+                CCPBKDFAlgorithm(kCCPBKDF2),
+                passphrase,
+                passphrase.utf8.count,
+                saltBytes.bindMemory(to: UInt8.self).baseAddress,
+                salt.count,
+                CCPseudoRandomAlgorithm(kCCPRFHmacAlgSHA256),
+                keyDerivationRounds,
+                &key,
+                key.count
+            )
+        }
+        guard Int(status) == kCCSuccess else {
+            throw ServerError.message("failed to derive the history encryption key")
+        }
+        return SymmetricKey(data: key)
+    }
+
+    private func encode(_ line: String, with key: (salt: Data, key: SymmetricKey)?) throws -> String {
+        guard let key else {
+            return line
+        }
+        let sealed = try ChaChaPoly.seal(Data(line.utf8), using: key.key)
+        return Self.encryptedLinePrefix + key.salt.base64EncodedString() + ":" + sealed.combined.base64EncodedString()
+    }
+
+    /// Plaintext lines pass through, so a history started before encryption was enabled stays readable.
+    private func decode(_ stored: String) throws -> String {
+        guard stored.hasPrefix(Self.encryptedLinePrefix) else {
+            return stored
+        }
+        guard let passphrase = try passphrase() else {
+            throw ServerError.message("history is encrypted; pass --history-encryption-key-env with the key variable")
+        }
+        let fields = stored.dropFirst(Self.encryptedLinePrefix.count).split(separator: ":", maxSplits: 1).map(String.init)
+        let undecryptable = ServerError.message("history cannot be decrypted; check the key in \(encryptionKeyEnv ?? "")")
+        guard fields.count == 2,
+              let salt = Data(base64Encoded: fields[0]),
+              let combined = Data(base64Encoded: fields[1]) else {
+            throw undecryptable
+        }
+        let key = try Self.derivedKeys.key(passphrase: passphrase, salt: salt) {
+            try Self.derivedKey(passphrase: passphrase, salt: salt)
+        }
+        guard let box = try? ChaChaPoly.SealedBox(combined: combined),
+              let plaintext = try? ChaChaPoly.open(box, using: key),
+              let line = String(data: plaintext, encoding: .utf8) else {
+            throw undecryptable
+        }
+        return line
+    }
+}
+
+/// History encryption keys by passphrase and salt, shared by every `History` in the process.
+final class HistoryKeyCache: @unchecked Sendable {
+    private let lock = NSLock()
+    private var keys: [String: SymmetricKey] = [:]
+
+    func key(passphrase: String, salt: Data, derive: () throws -> SymmetricKey) throws -> SymmetricKey {
+        lock.lock()
+        defer { lock.unlock() }
+        let cacheKey = passphrase + "\u{0}" + salt.base64EncodedString()
+        if let key = keys[cacheKey] {
+            return key
+        }
+        let key = try derive()
+        keys[cacheKey] = key
+        return key
+    }
 }
 
 func commandName(from query: String) -> String {
@@ -469,11 +1944,116 @@ func arguments(after binding: String, in query: String) -> String {
     return String(query.dropFirst(binding.count)).trimmingCharacters(in: .whitespacesAndNewlines)
 }
 
+/// `query` without its leading words when they are the words of `binding` (ignoring case), so binding
+/// `g` leaves `google maps` intact while `ig dm` strips `ig dm alice` to `alice`. Otherwise the whole
+/// query, trimmed. Backs the Lua `get_args` helper.
+func wholeWordArguments(after binding: String, in query: String) -> String {
+    let trimmed = query.trimmingCharacters(in: .whitespacesAndNewlines)
+    let bindingWords = binding.split(whereSeparator: \.isWhitespace)
+    var rest = Substring(trimmed)
+    for word in bindingWords {
+        let end = rest.firstIndex(where: \.isWhitespace) ?? rest.endIndex
+        guard rest[..<end].caseInsensitiveCompare(word) == .orderedSame else {
+            return trimmed
+        }
+        rest = rest[end...].drop(while: \.isWhitespace)
+    }
+    return bindingWords.isEmpty ? trimmed : String(rest)
+}
+
+/// Replaces `$*` with all of `arguments` and `$N` with the N-th whitespace-separated token (empty when
+/// missing). Returns nil when `template` has no placeholders.
+func substituteAliasPlaceholders(in template: String, arguments: String) -> String? {
+    let tokens = arguments.split(whereSeparator: { $0 == " " || $0 == "\t" }).map(String.init)
+    var result = ""
+    var foundPlaceholder = false
+    var index = template.startIndex
+    while index < template.endIndex {
+        let character = template[index]
+        let next = template.index(after: index)
+        guard character == "$", next < template.endIndex else {
+            result.append(character)
+            index = next
+            continue
+        }
+        if template[next] == "*" {
+            result += arguments
+            foundPlaceholder = true
+            index = template.index(after: next)
+            continue
+        }
+        let digits = template[next...].prefix(while: { $0.isASCII && $0.isNumber })
+        guard let position = Int(digits), position > 0 else {
+            result.append(character)
+            index = next
+            continue
+        }
+        result += position <= tokens.count ? tokens[position - 1] : ""
+        foundPlaceholder = true
+        index = digits.endIndex
+    }
+    return foundPlaceholder ? result : nil
+}
+
 func dataTextURL(text: String) -> String {
     "data:text/plain;charset=utf-8,\(percentEncode(text))"
 }
 
+/// Language tag such as `de-DE` from `LC_ALL`, `LC_MESSAGES` or `LANG`; nil for `C`/`POSIX` or when unset.
+func environmentLocale() -> String? {
+    let environment = ProcessInfo.processInfo.environment
+    guard let raw = ["LC_ALL", "LC_MESSAGES", "LANG"].lazy.compactMap({ environment[$0]?.nilIfEmpty }).first else {
+        return nil
+    }
+    let tag = raw.split(separator: ".").first.map(String.init)?.split(separator: "@").first.map(String.init) ?? raw
+    guard tag != "C", tag != "POSIX" else {
+        return nil
+    }
+    return tag.replacingOccurrences(of: "_", with: "-")
+}
+
+/// The highest-weighted language in an `Accept-Language` header, e.g. `de-CH` for `en;q=0.8, de-CH`.
+func preferredLanguage(acceptLanguage: String) -> String? {
+    let weighted = acceptLanguage.split(separator: ",").compactMap { item -> (tag: String, weight: Double)? in
+        let parts = item.split(separator: ";").map { $0.trimmingCharacters(in: .whitespaces) }
+        guard let tag = parts.first, !tag.isEmpty, tag != "*" else {
+            return nil
+        }
+        let weight = parts.dropFirst()
+            .first { $0.hasPrefix("q=") }
+            .flatMap { Double($0.dropFirst(2)) } ?? 1
+        return (tag, weight)
+    }
+    return weighted.max { $0.weight < $1.weight }?.tag
+}
+
+/// Where an encoded value goes in a URL, for `encode_for(s, context)`.
+enum URLEncodingContext: String {
+    /// A query parameter name or value: only unreserved characters are kept.
+    case query
+    /// One or more path segments: `/` is kept as well.
+    case path
+    /// The fragment after `#`: `/` and `?` are kept (RFC 3986, section 3.5).
+    case fragment
+
+    var keptPunctuation: Set<UInt8> {
+        switch self {
+        case .query:
+            return []
+        case .path:
+            return [UInt8(ascii: "/")]
+        case .fragment:
+            return [UInt8(ascii: "/"), UInt8(ascii: "?")]
+        }
+    }
+}
+
 func percentEncode(_ value: String, allowingSlash: Bool = false) -> String {
+    percentEncode(value, for: allowingSlash ? .path : .query)
+}
+
+func percentEncode(_ value: String, for context: URLEncodingContext) -> String {
+    let kept = context.keptPunctuation
     var result = ""
     for byte in value.utf8 {
         switch byte {
@@ -485,7 +2065,7 @@ func percentEncode(_ value: String, allowingSlash: Bool = false) -> String {
              UInt8(ascii: "_"),
              UInt8(ascii: "~"):
             result.append(Character(UnicodeScalar(byte)))
-        case UInt8(ascii: "/") where allowingSlash:
+        case _ where kept.contains(byte):
             result.append(Character(UnicodeScalar(byte)))
         default:
             result += String(format: "%%%02X", byte)
@@ -512,30 +2092,104 @@ private func luaStringArgument(_ values: [LuaValue], at index: Int = 0) -> Strin
     }
 }
 
-private func luaStringLiteral(_ value: String) -> String {
+private func luaTableLiteral(_ values: [String: String]) -> String {
+    let fields = values.keys.sorted().map { key in
+        "[\(luaStringLiteral(key))] = \(luaStringLiteral(values[key] ?? ""))"
+    }
+    return "{ \(fields.joined(separator: ", ")) }"
+}
+
+/// `value` as a double-quoted Lua string literal. Non-ASCII text is kept as is, since Lua strings are
+/// bytes and generated command files stay readable; other control characters become `\ddd` escapes.
+public func luaStringLiteral(_ value: String) -> String {
     var result = "\""
-    for byte in value.utf8 {
-        switch byte {
-        case UInt8(ascii: "\\"):
+    for scalar in value.unicodeScalars {
+        switch scalar {
+        case "\\":
             result += "\\\\"
-        case UInt8(ascii: "\""):
+        case "\"":
             result += "\\\""
-        case UInt8(ascii: "\n"):
+        case "\n":
             result += "\\n"
-        case UInt8(ascii: "\r"):
+        case "\r":
             result += "\\r"
-        case UInt8(ascii: "\t"):
+        case "\t":
             result += "\\t"
-        case 32...126:
-            result.append(Character(UnicodeScalar(byte)))
+        case " "..."~", "\u{80}"...:
+            result.unicodeScalars.append(scalar)
         default:
-            result += String(format: "\\%03d", byte)
+            result += String(format: "\\%03d", scalar.value)
         }
     }
     result += "\""
     return result
 }
 
+/// Contents of a `.lua` file, or of a `.lua.gz` file decompressed in memory.
+func luaSource(at url: URL) -> String? {
+    guard url.pathExtension == "gz" else {
+        return try? String(contentsOf: url, encoding: .utf8)
+    }
+    guard let data = try? Data(contentsOf: url), let inflated = gunzip(data) else {
+        return nil
+    }
+    return String(data: inflated, encoding: .utf8)
+}
+
+/// Whether a line of `source` starts a global definition of `name`, as `function name(` or
+/// `name = function`. Read from the text, so `local function name` and comments don't count.
+func luaSourceDefinesFunction(_ name: String, in source: String) -> Bool {
+    let escaped = NSRegularExpression.escapedPattern(for: name)
+    return source.range(
+        of: #"(?m)^[ \t]*(?:function[ \t]+\#(escaped)[ \t]*\(|\#(escaped)[ \t]*=[ \t]*function\b)"#,
+        options: .regularExpression
+    ) != nil
+}
+
+/// Contents of `relativePath` inside `directory`, or nil when it is unreadable or resolves, through
+/// `..` or a symlink, to somewhere outside `directory`.
+func dataFileContents(_ relativePath: String, in directory: URL) -> String? {
+    guard !relativePath.isEmpty, !relativePath.hasPrefix("/") else {
+        return nil
+    }
+    let root = directory.resolvingSymlinksInPath().standardizedFileURL.path
+    let file = directory.appendingPathComponent(relativePath).resolvingSymlinksInPath().standardizedFileURL
+    guard file.path.hasPrefix(root.hasSuffix("/") ? root : root + "/") else {
+        return nil
+    }
+    return try? String(contentsOf: file, encoding: .utf8)
+}
+
+/// Inflates a gzip file with zlib, which checks the header and the trailing CRC. Nil for anything that
+/// is not a complete, valid gzip stream.
+private func gunzip(_ data: Data) -> Data? {
+    var stream = z_stream()
+    // 15 window bits, plus 16 to expect a gzip wrapper rather than a zlib one.
+    guard inflateInit2_(&stream, 15 + 16, zlibVersion(), Int32(MemoryLayout<z_stream>.size)) == Z_OK else {
+        return nil
+    }
+    defer { inflateEnd(&stream) }
+
+    var input = [UInt8](data)
+    var buffer = [UInt8](repeating: 0, count: 64 * 1_024)
+    var output = Data()
+    var status = Z_OK
+    input.withUnsafeMutableBufferPointer { inputBytes in
+        stream.next_in = inputBytes.baseAddress
+        stream.avail_in = uInt(inputBytes.count)
+        // A truncated stream stops with Z_BUF_ERROR once the input runs out.
+        while status == Z_OK {
+            buffer.withUnsafeMutableBufferPointer { outputBytes in
+                stream.next_out = outputBytes.baseAddress
+                stream.avail_out = uInt(outputBytes.count)
+                status = inflate(&stream, Z_NO_FLUSH)
+                output.append(outputBytes.baseAddress!, count: outputBytes.count - Int(stream.avail_out))
+            }
+        }
+    }
+    return status == Z_STREAM_END ? output : nil
+}
+
 func percentDecode(_ value: String) -> String {
     value.replacingOccurrences(of: "+", with: " ").removingPercentEncoding ?? value
 }
@@ -126,6 +126,16 @@ public struct HTTPResponse {
         self.body = body
     }
 
+    /// This response with `headers` added, replacing any with the same name.
+    public func addingHeaders(_ headers: [String: String]) -> HTTPResponse {
+        HTTPResponse(
+            statusCode: statusCode,
+            reason: reason,
+            headers: self.headers.merging(headers) { _, added in added },
+            body: body
+        )
+    }
+
     public static func text(
         _ text: String,
         statusCode: Int = 200,
@@ -140,10 +150,10 @@ public struct HTTPResponse {
         )
     }
 
-    public static func html(_ html: String) -> HTTPResponse {
+    public static func html(_ html: String, statusCode: Int = 200, reason: String = "OK") -> HTTPResponse {
         HTTPResponse(
-            statusCode: 200,
-            reason: "OK",
+            statusCode: statusCode,
+            reason: reason,
             headers: ["Content-Type": "text/html; charset=utf-8"],
             body: Data(html.utf8)
         )
@@ -158,11 +168,11 @@ public struct HTTPResponse {
         )
     }
 
-    public static func redirect(to location: String) -> HTTPResponse {
+    public static func redirect(to location: String, headers: [String: String] = [:]) -> HTTPResponse {
         HTTPResponse(
             statusCode: 302,
             reason: "Found",
-            headers: ["Location": location],
+            headers: headers.merging(["Location": location]) { _, location in location },
             body: Data()
         )
     }
@@ -176,20 +186,52 @@ public final class SimpleHTTPServer: @unchecked Sendable {
     private let maxBodyBytes: Int
     private let handler: Handler
     private let clientQueue = DispatchQueue(label: "lolabunny.http.clients", qos: .userInitiated, attributes: .concurrent)
+    private let workerSlots: DispatchSemaphore?
+    private let ident: String?
+    private let shutdownGrace: TimeInterval
+    private let shutdownMercy: TimeInterval
+    private let clientTimeout: TimeInterval
+    private let idleTimeout: TimeInterval?
+    private let onShutdown: () -> Void
+    private let inFlight = DispatchGroup()
+    private let clientsLock = NSLock()
+    private var clientFDs = Set<Int32>()
+    private var isDraining = false
+    private var listeningFD: Int32 = -1
+    private let stopped = DispatchSemaphore(value: 0)
+    private var signalSources: [DispatchSourceSignal] = []
+    private var lastActivity = Date()
+    private var idleTimer: DispatchSourceTimer?
 
     public init(
         address: String,
         port: UInt16,
         maxBodyBytes: Int = 64 * 1024 * 1024,
+        workers: Int? = nil,
+        ident: String? = nil,
+        shutdownGrace: TimeInterval = 5,
+        shutdownMercy: TimeInterval = 1,
+        clientTimeout: TimeInterval = 5,
+        idleTimeout: TimeInterval? = nil,
+        onShutdown: @escaping () -> Void = {},
         handler: @escaping Handler
     ) {
         self.address = address
         self.port = port
         self.maxBodyBytes = maxBodyBytes
+        self.ident = ident
+        self.shutdownGrace = shutdownGrace
+        self.shutdownMercy = shutdownMercy
+        self.clientTimeout = clientTimeout
+        self.idleTimeout = idleTimeout
+        self.onShutdown = onShutdown
+        self.workerSlots = workers.map { DispatchSemaphore(value: max(1, $0)) }
         self.handler = handler
     }
 
-    public func run() throws -> Never {
+    /// Binds and serves until `stop()` has drained the server. With `portRetries`, an in-use port is
+    /// retried on the next ports up; `onListening` receives the port actually bound.
+    public func run(portRetries: Int = 0, onListening: (UInt16) -> Void = { _ in }) throws {
         let serverFD = socket(AF_INET, SOCK_STREAM, 0)
         guard serverFD >= 0 else {
             throw ServerError.message("socket failed")
@@ -205,23 +247,88 @@ public final class SimpleHTTPServer: @unchecked Sendable {
         var socketAddress = sockaddr_in()
         socketAddress.sin_len = UInt8(MemoryLayout<sockaddr_in>.size)
         socketAddress.sin_family = sa_family_t(AF_INET)
-        socketAddress.sin_port = port.bigEndian
         guard inet_pton(AF_INET, address, &socketAddress.sin_addr) == 1 else {
             throw ServerError.message("invalid bind address: \(address)")
         }
 
+        var boundPort = port
+        while true {
+            socketAddress.sin_port = boundPort.bigEndian
+            let bindResult = withUnsafePointer(to: &socketAddress) { pointer in
+                pointer.withMemoryRebound(to: sockaddr.self, capacity: 1) { socketPointer in
+                    bind(serverFD, socketPointer, socklen_t(MemoryLayout<sockaddr_in>.size))
+                }
+            }
+            if bindResult == 0 {
+                break
+            }
+            let bindErrno = errno
+            guard bindErrno == EADDRINUSE,
+                  Int(boundPort) - Int(port) < portRetries,
+                  boundPort < UInt16.max else {
+                throw ServerError.message(bindErrorMessage(errno: bindErrno, address: address, port: boundPort))
+            }
+            boundPort += 1
+        }
+
+        guard listen(serverFD, SOMAXCONN) == 0 else {
+            throw ServerError.message("listen failed: \(String(cString: strerror(errno)))")
+        }
+        onListening(boundPort)
+        serve(serverFD: serverFD)
+    }
+
+    /// Like `run(portRetries:onListening:)`, but listens on a Unix domain socket at `path`. A socket left
+    /// at `path` by an earlier run is removed first; any other file there is an error.
+    public func run(unixSocket path: String, onListening: () -> Void = {}) throws {
+        let serverFD = socket(AF_UNIX, SOCK_STREAM, 0)
+        guard serverFD >= 0 else {
+            throw ServerError.message("socket failed")
+        }
+        defer { close(serverFD) }
+
+        var noSIGPipe: Int32 = 1
+        setsockopt(serverFD, SOL_SOCKET, SO_NOSIGPIPE, &noSIGPipe, socklen_t(MemoryLayout<Int32>.size))
+
+        var socketAddress = sockaddr_un()
+        socketAddress.sun_len = UInt8(MemoryLayout<sockaddr_un>.size)
+        socketAddress.sun_family = sa_family_t(AF_UNIX)
+        let pathBytes = Array(path.utf8)
+        guard !pathBytes.isEmpty, pathBytes.count < MemoryLayout.size(ofValue: socketAddress.sun_path) else {
+            throw ServerError.message("invalid unix socket path: \(path)")
+        }
+        withUnsafeMutableBytes(of: &socketAddress.sun_path) { $0.copyBytes(from: pathBytes) }
+
+        var existing = stat()
+        if lstat(path, &existing) == 0, existing.st_mode & S_IFMT == S_IFSOCK {
+            unlink(path)
+        }
+
         let bindResult = withUnsafePointer(to: &socketAddress) { pointer in
             pointer.withMemoryRebound(to: sockaddr.self, capacity: 1) { socketPointer in
-                bind(serverFD, socketPointer, socklen_t(MemoryLayout<sockaddr_in>.size))
+                bind(serverFD, socketPointer, socklen_t(MemoryLayout<sockaddr_un>.size))
             }
         }
         guard bindResult == 0 else {
-            throw ServerError.message("bind failed on \(address):\(port): \(String(cString: strerror(errno)))")
+            throw ServerError.message("bind failed on \(path): \(String(cString: strerror(errno)))")
         }
-
         guard listen(serverFD, SOMAXCONN) == 0 else {
             throw ServerError.message("listen failed: \(String(cString: strerror(errno)))")
         }
+        onListening()
+        serve(serverFD: serverFD)
+    }
+
+    private func serve(serverFD: Int32) {
+        clientsLock.lock()
+        listeningFD = serverFD
+        let stoppedEarly = isDraining
+        clientsLock.unlock()
+        if stoppedEarly {
+            stopped.wait()
+            return
+        }
+        installIdleTimer()
 
         while true {
             var clientAddress = sockaddr_storage()
@@ -233,22 +340,130 @@ public final class SimpleHTTPServer: @unchecked Sendable {
             }
 
             guard clientFD >= 0 else {
+                if draining {
+                    // `stop()` signals once in-flight requests are done.
+                    stopped.wait()
+                    return
+                }
                 continue
             }
 
             configureClientSocket(clientFD)
+            workerSlots?.wait()
+            guard beginClient(clientFD) else {
+                close(clientFD)
+                workerSlots?.signal()
+                continue
+            }
             clientQueue.async { [self] in
                 handleClient(clientFD)
-                close(clientFD)
+                endClient(clientFD)
+                workerSlots?.signal()
             }
         }
     }
 
+    private var draining: Bool {
+        clientsLock.lock()
+        defer { clientsLock.unlock() }
+        return isDraining
+    }
+
+    private func beginClient(_ fd: Int32) -> Bool {
+        clientsLock.lock()
+        defer { clientsLock.unlock() }
+        guard !isDraining else {
+            return false
+        }
+        clientFDs.insert(fd)
+        lastActivity = Date()
+        inFlight.enter()
+        return true
+    }
+
+    private func endClient(_ fd: Int32) {
+        clientsLock.lock()
+        clientFDs.remove(fd)
+        lastActivity = Date()
+        clientsLock.unlock()
+        close(fd)
+        inFlight.leave()
+    }
+
+    /// Calls `stop()` on SIGTERM or SIGINT instead of letting them end the process. This replaces the
+    /// process-wide handlers for both signals, so only a process that is the server itself opts in.
+    public func stopOnTerminationSignals() {
+        let queue = DispatchQueue(label: "lolabunny.http.shutdown")
+        signalSources = [SIGTERM, SIGINT].map { signalNumber in
+            signal(signalNumber, SIG_IGN)
+            let source = DispatchSource.makeSignalSource(signal: signalNumber, queue: queue)
+            source.setEventHandler { [self] in
+                stop()
+            }
+            source.resume()
+            return source
+        }
+    }
+
+    /// With `idleTimeout`, stops like `stop()` once no request has been seen for that long.
+    private func installIdleTimer() {
+        guard let idleTimeout else {
+            return
+        }
+        let timer = DispatchSource.makeTimerSource(queue: DispatchQueue(label: "lolabunny.http.idle"))
+        let interval = min(1, idleTimeout / 4)
+        timer.schedule(deadline: .now() + interval, repeating: interval)
+        timer.setEventHandler { [self] in
+            clientsLock.lock()
+            let idle = clientFDs.isEmpty && Date().timeIntervalSince(lastActivity) >= idleTimeout
+            clientsLock.unlock()
+            if idle {
+                print("Shutting down after \(idleTimeout)s without requests")
+                fflush(stdout)
+                stop()
+            }
+        }
+        timer.resume()
+        idleTimer = timer
+    }
+
+    /// Stops accepting, lets in-flight requests finish for `shutdownGrace`, then shuts their sockets
+    /// down and waits up to `shutdownMercy` more. `run` returns once this is done. Blocks until then;
+    /// later calls return at once.
+    public func stop() {
+        clientsLock.lock()
+        guard !isDraining else {
+            clientsLock.unlock()
+            return
+        }
+        isDraining = true
+        let serverFD = listeningFD
+        clientsLock.unlock()
+        idleTimer?.cancel()
+        if serverFD >= 0 {
+            Darwin.shutdown(serverFD, SHUT_RDWR)
+        }
+
+        if inFlight.wait(timeout: .now() + shutdownGrace) == .timedOut {
+            clientsLock.lock()
+            let remaining = clientFDs
+            clientsLock.unlock()
+            fputs("Warning: shutting down \(remaining.count) request(s) still running after \(shutdownGrace)s\n", stderr)
+            for fd in remaining {
+                Darwin.shutdown(fd, SHUT_RDWR)
+            }
+            _ = inFlight.wait(timeout: .now() + shutdownMercy)
+        }
+        onShutdown()
+        stopped.signal()
+    }
+
     private func configureClientSocket(_ fd: Int32) {
         var noSIGPipe: Int32 = 1
         setsockopt(fd, SOL_SOCKET, SO_NOSIGPIPE, &noSIGPipe, socklen_t(MemoryLayout<Int32>.size))
 
-        var timeout = timeval(tv_sec: 5, tv_usec: 0)
+        let seconds = clientTimeout.rounded(.down)
+        var timeout = timeval(tv_sec: Int(seconds), tv_usec: Int32((clientTimeout - seconds) * 1_000_000))
         setsockopt(fd, SOL_SOCKET, SO_RCVTIMEO, &timeout, socklen_t(MemoryLayout<timeval>.size))
         setsockopt(fd, SOL_SOCKET, SO_SNDTIMEO, &timeout, socklen_t(MemoryLayout<timeval>.size))
     }
@@ -266,6 +481,9 @@ public final class SimpleHTTPServer: @unchecked Sendable {
         var headers = response.headers
         headers["Content-Length"] = "\(response.body.count)"
         headers["Connection"] = "close"
+        if let ident, headers["Server"] == nil {
+            headers["Server"] = ident
+        }
 
         var head = "HTTP/1.1 \(response.statusCode) \(response.reason)\r\n"
         for (key, value) in headers {
@@ -297,27 +515,70 @@ public final class SimpleHTTPServer: @unchecked Sendable {
     }
 }
 
-public final class HTTPServer {
+public final class HTTPServer: @unchecked Sendable {
     private let address: String
-    private let port: UInt16
-    private let router: CommandRouter
     private let config: AppConfig
+    private let server: SimpleHTTPServer
 
     public init(address: String, port: UInt16, router: CommandRouter, config: AppConfig) {
         self.address = address
-        self.port = port
-        self.router = router
         self.config = config
+        self.server = SimpleHTTPServer(
+            address: address,
+            port: port,
+            workers: config.server.workers,
+            ident: config.server.ident ?? "Lolabunny/\(Paths.versionString())",
+            shutdownGrace: config.server.shutdownGrace,
+            shutdownMercy: config.server.shutdownMercy,
+            clientTimeout: config.server.clientTimeout,
+            idleTimeout: config.server.idleTimeout,
+            onShutdown: History.flushPendingWrites
+        ) { [router, config] request in
+            let response = Self.response(for: request, router: router, config: config)
+            guard config.server.securityHeaders,
+                  response.headers["Content-Type"]?.hasPrefix("text/html") == true else {
+                return response
+            }
+            return response.addingHeaders(Self.securityHeaders)
+        }
     }
 
-    public func run() throws -> Never {
-        print("Lolabunny listening on \(address):\(port)")
-        let server = SimpleHTTPServer(address: address, port: port) { [router, config] request in
-            Self.response(for: request, router: router, config: config)
+    /// Serves until `stop()` is called, or until SIGTERM/SIGINT after `stopOnTerminationSignals()`.
+    /// `onListening` runs once the socket is bound and accepting connections.
+    public func run(onListening: () -> Void = {}) throws {
+        if let unixSocket = config.server.unixSocket {
+            try server.run(unixSocket: unixSocket) {
+                print("Lolabunny listening on unix:\(unixSocket)")
+                onListening()
+            }
+            return
+        }
+        try server.run(portRetries: config.server.portRetries) { [address] boundPort in
+            print("Lolabunny listening on \(address):\(boundPort)")
+            onListening()
         }
-        try server.run()
     }
 
+    /// Drains in-flight requests, flushes pending history writes and makes `run()` return.
+    public func stop() {
+        server.stop()
+    }
+
+    /// Makes SIGTERM and SIGINT stop the server rather than end the process. Only for a process
+    /// that exists to run the server, such as `lolabunny serve`.
+    public func stopOnTerminationSignals() {
+        server.stopOnTerminationSignals()
+    }
+
+    /// The bindings page uses inline styles and scripts, embedded images and same-origin API calls only.
+    private static let securityHeaders = [
+        "Content-Security-Policy": "default-src 'none'; img-src data:; style-src 'unsafe-inline'; "
+            + "script-src 'unsafe-inline'; connect-src 'self'; base-uri 'none'; form-action 'self'; "
+            + "frame-ancestors 'none'",
+        "X-Content-Type-Options": "nosniff",
+        "Referrer-Policy": "no-referrer",
+    ]
+
     private static func response(for request: HTTPRequest, router: CommandRouter, config: AppConfig) -> HTTPResponse {
         switch (request.method, request.path) {
         case ("GET", "/health"):
@@ -326,37 +587,167 @@ public final class HTTPServer {
             return .json(commandsJSON(router: router))
         case ("GET", "/api/resolve"):
             return resolveCommandRequest(request, router: router, config: config)
+        case ("POST", "/api/resolve/batch"):
+            return resolveBatchRequest(request, router: router, config: config)
         case ("GET", "/api/suggest"):
             return suggestCommandArguments(request, router: router)
         case ("GET", "/api/search-suggestions"), ("GET", "/suggest"):
-            return suggestSearchTerms(request, router: router)
+            return suggestSearchTerms(request, router: router, limit: config.server.suggestLimit)
+        case ("GET", "/opensearch.xml"):
+            return .text(
+                openSearchXML(displayURL: config.server.displayURL),
+                contentType: "application/opensearchdescription+xml; charset=utf-8"
+            )
+        case ("GET", "/debug/config") where config.server.debugEndpoints:
+            return .json(configJSON(config))
         case ("GET", "/"):
             return handleCommandRequest(request, router: router, config: config)
         default:
-            return .html(bindingsHTML(router: router))
+            return notFound(request, router: router, config: config)
         }
     }
 
+    /// Served for unknown paths without a `notFoundTemplate`, or when it cannot be read.
+    private static let defaultNotFoundTemplate = """
+    <!doctype html>
+    <html><head><meta charset="utf-8"><title>Not found</title></head>
+    <body><h1>Not found</h1><p>Nothing at <code>__PATH__</code>. See the <a href="/">command list</a>.</p></body>
+    </html>
+    """
+
+    private static func notFound(_ request: HTTPRequest, router: CommandRouter, config: AppConfig) -> HTTPResponse {
+        var template = defaultNotFoundTemplate
+        if let templatePath = config.server.notFoundTemplate {
+            if let custom = try? String(contentsOfFile: templatePath, encoding: .utf8) {
+                template = custom
+            } else {
+                fputs("Warning: Failed to read not-found template \(templatePath)\n", stderr)
+            }
+        }
+        let html = template.replacingOccurrences(of: "__PATH__", with: htmlEscape(request.path))
+        return .html(html, statusCode: 404, reason: "Not Found")
+    }
+
     private static func handleCommandRequest(_ request: HTTPRequest, router: CommandRouter, config: AppConfig) -> HTTPResponse {
         guard let query = request.query["cmd"] else {
+            if let rootRedirect = config.server.rootRedirect {
+                return .redirect(to: rootRedirect)
+            }
             return .html(bindingsHTML(router: router))
+                .addingHeaders(["Link": "</opensearch.xml>; rel=\"search\"; type=\"application/opensearchdescription+xml\""])
         }
 
-        let location = router.route(query, config: config)
+        let route = router.resolveRoute(
+            query,
+            config: config,
+            parameters: commandParameters(request),
+            locale: requestLocale(request)
+        )
+        if let error = route.error {
+            return .html(commandErrorHTML(query: query, message: error), statusCode: 400, reason: "Bad Request")
+        }
+        if route.searchSkipped {
+            return .html(bindingsHTML(router: router))
+        }
         if config.history.enabled {
-            History(config: config).add(command: query, user: request.headers["x-forwarded-for"] ?? "localhost")
+            let history = History(config: config)
+            let user = request.headers["x-forwarded-for"] ?? "localhost"
+            if config.history.asyncWrites {
+                history.addInBackground(command: query, user: user, url: route.location)
+            } else {
+                history.add(command: query, user: user, url: route.location)
+            }
+        }
+        let headers = config.server.debugEndpoints ? ["X-Lolabunny-Matched": matchedHeaderValue(route)] : [:]
+        guard route.locations.count == 1 else {
+            // A redirect can only go to one place, so link to all of them instead.
+            return .html(commandLinksHTML(query: query, urls: route.locations, labels: route.labels)).addingHeaders(headers)
+        }
+        return .redirect(to: route.location, headers: headers)
+    }
+
+    private static func commandLinksHTML(query: String, urls: [String], labels: [String]) -> String {
+        let items = zip(urls, labels).map { url, label in
+            let link = "<a href=\"\(htmlAttributeEscape(url))\" target=\"_blank\" rel=\"noreferrer\">\(htmlEscape(label))</a>"
+            return label == url ? "<li>\(link)</li>" : "<li>\(link) <small>\(htmlEscape(url))</small></li>"
+        }
+        return """
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>lolabunny: \(htmlEscape(commandName(from: query)))</title></head>
+        <body style="font-family: -apple-system, sans-serif; margin: 48px auto; max-width: 560px;">
+        <p><code>\(htmlEscape(query))</code></p>
+        <ul>
+        \(items.joined(separator: "\n"))
+        </ul>
+        </body>
+        </html>
+        """
+    }
+
+    private static func commandErrorHTML(query: String, message: String) -> String {
+        """
+        <!DOCTYPE html>
+        <html lang="en">
+        <head><meta charset="UTF-8"><title>lolabunny: \(htmlEscape(commandName(from: query)))</title></head>
+        <body style="font-family: -apple-system, sans-serif; margin: 48px auto; max-width: 560px;">
+        <p><code>\(htmlEscape(query))</code></p>
+        <p>\(htmlEscape(message))</p>
+        </body>
+        </html>
+        """
+    }
+
+    private static func matchedHeaderValue(_ route: CommandRoute) -> String {
+        guard let binding = route.binding else {
+            return "none"
         }
-        return .redirect(to: location)
+        return "\(binding); origin=\(route.origin ?? "unknown")"
+    }
+
+    private static func requestLocale(_ request: HTTPRequest) -> String? {
+        request.headers["accept-language"].flatMap(preferredLanguage(acceptLanguage:))
+    }
+
+    /// Query parameters other than the command itself, passed through to the command's `process`.
+    private static func commandParameters(_ request: HTTPRequest) -> [String: String] {
+        request.query.filter { $0.key != "cmd" && $0.key != "q" }
     }
 
     private static func resolveCommandRequest(_ request: HTTPRequest, router: CommandRouter, config: AppConfig) -> HTTPResponse {
         let query = request.query["cmd"] ?? ""
-        let location = router.route(query, config: config)
+        let location = router.route(query, config: config, parameters: commandParameters(request), locale: requestLocale(request))
         return .json("""
         {"query":\(jsonString(query)),"location":\(jsonString(location)),"kind":\(jsonString(locationKind(location)))}
         """)
     }
 
+    /// Resolves a JSON array of commands in order, without recording history.
+    private static func resolveBatchRequest(_ request: HTTPRequest, router: CommandRouter, config: AppConfig) -> HTTPResponse {
+        guard let commands = (try? JSONSerialization.jsonObject(with: request.body)) as? [String] else {
+            return .text("expected a JSON array of command strings", statusCode: 400, reason: "Bad Request")
+        }
+        // Like the CLI: an error or a skipped search is reported instead of a URL, and a command that
+        // opens several URLs lists them all in `urls`.
+        let results = commands.map { command in
+            let route = router.resolveRoute(command, config: config)
+            var fields = [
+                "\"input\":\(jsonString(command))",
+                "\"url\":\(jsonString(route.error == nil ? route.location : ""))",
+                "\"matched\":\(route.matched)",
+            ]
+            if let error = route.error {
+                fields.append("\"error\":\(jsonString(error))")
+            } else if route.searchSkipped {
+                fields.append("\"searchSkipped\":true")
+            } else if route.locations.count > 1 {
+                fields.append("\"urls\":[\(route.locations.map(jsonString).joined(separator: ","))]")
+            }
+            return "{\(fields.joined(separator: ","))}"
+        }
+        return .json("[\(results.joined(separator: ","))]")
+    }
+
     private static func suggestCommandArguments(_ request: HTTPRequest, router: CommandRouter) -> HTTPResponse {
         let binding = request.query["cmd"] ?? ""
         let query = request.query["q"] ?? ""
@@ -375,14 +766,15 @@ public final class HTTPServer {
         return .json("[\(suggestions.prefix(8).map(jsonString).joined(separator: ","))]")
     }
 
-    private static func suggestSearchTerms(_ request: HTTPRequest, router: CommandRouter) -> HTTPResponse {
+    private static func suggestSearchTerms(_ request: HTTPRequest, router: CommandRouter, limit: Int) -> HTTPResponse {
         let query = request.query["q"] ?? request.query["searchTerms"] ?? ""
-        let suggestions = searchSuggestions(for: query, router: router)
+        let suggestions = searchSuggestions(for: query, router: router, limit: limit)
         return .json("[\(jsonString(query)),[\(suggestions.map(jsonString).joined(separator: ","))]]")
     }
 
-    private static func bindingsHTML(router: CommandRouter) -> String {
-        let commands = router.allCommands()
+    /// The bindings page served at `/`, also usable headlessly to publish it as a static file.
+    public static func bindingsHTML(router: CommandRouter) -> String {
+        let commands = router.allCommandsWithLiveExamples()
         let rows = commands.map { command in
             let binding = htmlEscape(command.bindings.first ?? "")
             let aliases = htmlEscape(command.bindings.dropFirst().joined(separator: ", "))
@@ -404,33 +796,168 @@ public final class HTTPServer {
             """
         }.joined(separator: "\n")
 
-        return bindingsTemplate()
-            .replacingOccurrences(of: "__LOGO__", with: logoBase64())
-            .replacingOccurrences(of: "__COMMAND_COUNT__", with: "\(commands.count)")
-            .replacingOccurrences(of: "__COMMAND_ROWS__", with: rows)
-            .replacingOccurrences(of: "__COMMANDS_JSON__", with: commandsJSON(router: router))
-            .replacingOccurrences(of: "__VERSION__", with: htmlEscape(Paths.versionString()))
+        // One pass, so placeholder names inside command text are left alone rather than expanded.
+        return fillTemplate(bindingsTemplate(), with: [
+            "__LOGO__": logoBase64(),
+            "__COMMAND_COUNT__": "\(commands.count)",
+            "__COMMAND_ROWS__": rows,
+            "__COMMANDS_JSON__": scriptSafeJSON(commandsJSON(commands)),
+            "__VERSION__": htmlEscape(Paths.versionString()),
+        ])
     }
 
 }
 
+func bindErrorMessage(errno code: Int32, address: String, port: UInt16) -> String {
+    guard code == EADDRINUSE else {
+        return "bind failed on \(address):\(port): \(String(cString: strerror(code)))"
+    }
+    return "\(address):\(port) is already in use. Another lolabunny-server may already be running; "
+        + "stop it, choose a different --port, or pass --port-retry COUNT to try the next ports."
+}
+
+/// Replaces each `__NAME__` placeholder in `template` that has a value; others are kept as is.
+func fillTemplate(_ template: String, with values: [String: String]) -> String {
+    guard let placeholder = try? NSRegularExpression(pattern: "__[A-Z_]+?__") else {
+        return template
+    }
+    let ns = template as NSString
+    var result = ""
+    var position = 0
+    for match in placeholder.matches(in: template, range: NSRange(location: 0, length: ns.length)) {
+        guard let value = values[ns.substring(with: match.range)] else {
+            continue
+        }
+        result += ns.substring(with: NSRange(location: position, length: match.range.location - position)) + value
+        position = match.range.location + match.range.length
+    }
+    return result + ns.substring(from: position)
+}
+
+/// JSON safe to embed in an inline `<script>`: `<`, `>` and `&` become `\u` escapes, so command text
+/// such as `</script>` can't end the script early.
+func scriptSafeJSON(_ json: String) -> String {
+    json
+        .replacingOccurrences(of: "<", with: "\\u003C")
+        .replacingOccurrences(of: ">", with: "\\u003E")
+        .replacingOccurrences(of: "&", with: "\\u0026")
+}
+
 func commandsJSON(router: CommandRouter) -> String {
-    let commands = router.allCommands().map { command in
+    commandsJSON(router.allCommands())
+}
+
+func commandsJSON(_ commands: [CommandInfo]) -> String {
+    let commands = commands.map { command in
         """
-        {"bindings":[\(command.bindings.map(jsonString).joined(separator: ","))],"description":\(jsonString(command.description)),"example":\(jsonString(command.example)),"origin":\(jsonString(command.origin)),"suggestURL":\(command.suggestURL.map(jsonString) ?? "null")}
+        {"bindings":[\(command.bindings.map(jsonString).joined(separator: ","))],"description":\(jsonString(command.description)),"example":\(jsonString(command.example)),"origin":\(jsonString(command.origin)),"suggestURL":\(command.suggestURL.map(jsonString) ?? "null"),"author":\(command.author.map(jsonString) ?? "null"),"version":\(command.version.map(jsonString) ?? "null"),"homepage":\(command.homepage.map(jsonString) ?? "null")}
         """
     }
     return "[\(commands.joined(separator: ","))]"
 }
 
-func searchSuggestions(for rawQuery: String, router: CommandRouter) -> [String] {
+/// Lets browsers add the server as a search engine, with `/suggest` for address bar completions.
+func openSearchXML(displayURL: String) -> String {
+    let search = htmlAttributeEscape("\(displayURL)/?cmd={searchTerms}")
+    let suggest = htmlAttributeEscape("\(displayURL)/suggest?q={searchTerms}")
+    return """
+    <?xml version="1.0" encoding="UTF-8"?>
+    <OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+      <ShortName>lolabunny</ShortName>
+      <Description>Lolabunny commands</Description>
+      <InputEncoding>UTF-8</InputEncoding>
+      <Url type="text/html" method="get" template="\(search)"/>
+      <Url type="application/x-suggestions+json" method="get" template="\(suggest)"/>
+    </OpenSearchDescription>
+
+    """
+}
+
+/// The effective configuration for `/debug/config`. Secrets, and credentials embedded in URLs, are
+/// replaced with `"<redacted>"`.
+func configJSON(_ config: AppConfig) -> String {
+    let redacted = "<redacted>"
+    /// Names of variables holding secrets are hidden too; only whether one is configured shows.
+    func secret(_ value: String?) -> Any {
+        value == nil ? NSNull() : redacted
+    }
+    func url(_ value: String?) -> Any {
+        guard let value else {
+            return NSNull()
+        }
+        let components = URLComponents(string: value)
+        return components?.user == nil && components?.password == nil ? value : redacted
+    }
+    func optional(_ value: Any?) -> Any {
+        value ?? NSNull()
+    }
+    let history = config.history
+    let server = config.server
+    let object: [String: Any] = [
+        "browser": optional(config.browser),
+        "openBehavior": "\(config.openBehavior)",
+        "openTimeout": config.openTimeout,
+        "defaultSearch": url(config.defaultSearch),
+        "searchPrivacy": config.searchPrivacy,
+        "ddgBangs": config.ddgBangs,
+        "searchOverrides": config.searchOverrides,
+        "kagiTokenEnv": secret(config.kagiTokenEnv),
+        "aliases": config.aliases,
+        "defaultArguments": config.defaultArguments,
+        "commandMeta": config.commandMeta.mapValues { meta in
+            ["description": optional(meta.description), "example": optional(meta.example)] as [String: Any]
+        },
+        "commandPrefix": optional(config.commandPrefix),
+        "minSearchLength": config.minSearchLength,
+        "cleanURLParams": config.cleanURLParams,
+        "history": [
+            "enabled": history.enabled,
+            "maxEntries": history.maxEntries,
+            "dedup": history.dedup,
+            "storeURL": history.storeURL,
+            "encryptionKeyEnv": secret(history.encryptionKeyEnv),
+            "asyncWrites": history.asyncWrites,
+        ] as [String: Any],
+        "server": [
+            "port": Int(server.port),
+            "address": server.address,
+            "logLevel": server.logLevel,
+            "volumePath": optional(server.volumePath),
+            "notFoundTemplate": optional(server.notFoundTemplate),
+            "workers": optional(server.workers),
+            "portRetries": server.portRetries,
+            "publicURL": url(server.publicURL),
+            "rootRedirect": url(server.rootRedirect),
+            "ident": optional(server.ident),
+            "debugEndpoints": server.debugEndpoints,
+            "shutdownGrace": server.shutdownGrace,
+            "shutdownMercy": server.shutdownMercy,
+            "securityHeaders": server.securityHeaders,
+            "clientTimeout": server.clientTimeout,
+            "idleTimeout": optional(server.idleTimeout),
+            "unixSocket": optional(server.unixSocket),
+            "suggestLimit": server.suggestLimit,
+            "publicURLScheme": optional(server.publicURLScheme),
+        ] as [String: Any],
+        "pluginScan": ["maxDepth": config.pluginScan.maxDepth, "maxFiles": config.pluginScan.maxFiles],
+        "safeMode": config.safeMode,
+    ]
+    guard let data = try? JSONSerialization.data(withJSONObject: object, options: [.sortedKeys]) else {
+        return "{}"
+    }
+    return String(decoding: data, as: UTF8.self)
+}
+
+/// Completions for the browser address bar: a command's example and remote suggestions once the query
+/// names a binding, otherwise matching commands, those whose binding starts with the query first.
+func searchSuggestions(for rawQuery: String, router: CommandRouter, limit: Int = 10) -> [String] {
     let query = rawQuery
         .trimmingCharacters(in: .whitespacesAndNewlines)
         .dropLeadingSlash()
     let commands = router.allCommands()
 
     guard !query.isEmpty else {
-        return deduplicatedSuggestions(commands.compactMap(\.example).filter { !$0.isEmpty })
+        return deduplicatedSuggestions(commands.compactMap(\.example).filter { !$0.isEmpty }, limit: limit)
     }
 
     let binding = commandName(from: query).lowercased()
@@ -452,15 +979,19 @@ func searchSuggestions(for rawQuery: String, router: CommandRouter) -> [String]
             }
             return alreadyComplete ? trimmed : "\(primary) \(trimmed)"
         })
-        return deduplicatedSuggestions(suggestions)
+        return deduplicatedSuggestions(suggestions, limit: limit)
     }
 
-    let matches = commands.filter { command in
+    let prefixMatches = commands.filter { command in
         command.bindings.contains { $0.lowercased().hasPrefix(binding) }
-            || command.description.lowercased().contains(binding)
-            || command.example.lowercased().contains(binding)
     }
-    return deduplicatedSuggestions(matches.compactMap { command in
+    let containsMatches = commands.filter { command in
+        !command.bindings.contains { $0.lowercased().hasPrefix(binding) }
+            && (command.bindings.contains { $0.lowercased().contains(binding) }
+                || command.description.lowercased().contains(binding)
+                || command.example.lowercased().contains(binding))
+    }
+    return deduplicatedSuggestions((prefixMatches + containsMatches).compactMap { command in
         if !command.example.isEmpty {
             return command.example
         }
@@ -468,7 +999,7 @@ func searchSuggestions(for rawQuery: String, router: CommandRouter) -> [String]
             return nil
         }
         return primary + " "
-    })
+    }, limit: limit)
 }
 
 func remoteArgumentSuggestions(for command: CommandInfo, query: String) -> [String] {
@@ -544,7 +1075,9 @@ func logoBase64() -> String {
     return data.base64EncodedString()
 }
 
-func htmlEscape(_ value: String) -> String {
+/// `value` escaped for HTML text and double-quoted attributes. Shared with bookmark export so the
+/// bindings page and the bookmarks file escape alike.
+public func htmlEscape(_ value: String) -> String {
     value
         .replacingOccurrences(of: "&", with: "&amp;")
         .replacingOccurrences(of: "<", with: "&lt;")
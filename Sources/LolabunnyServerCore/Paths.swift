@@ -16,6 +16,11 @@ public enum Paths {
         runtimeDirectory.appendingPathComponent("pid")
     }
 
+    /// Output of a server started with `serve --daemon`.
+    public static var daemonLogFile: URL {
+        runtimeDirectory.appendingPathComponent("server.log")
+    }
+
     public static var dataHome: URL {
         if let raw = ProcessInfo.processInfo.environment["XDG_DATA_HOME"],
            !raw.trimmingCharacters(in: .whitespacesAndNewlines).isEmpty {
@@ -38,6 +43,29 @@ public enum Paths {
         appDataHome.appendingPathComponent("history")
     }
 
+    /// Cache home per the XDG base directory spec, `~/.cache` by default.
+    public static var cacheHome: URL {
+        if let raw = ProcessInfo.processInfo.environment["XDG_CACHE_HOME"],
+           !raw.trimmingCharacters(in: .whitespacesAndNewlines).isEmpty {
+            return URL(fileURLWithPath: (raw as NSString).expandingTildeInPath, isDirectory: true)
+        }
+        return homeDirectory.appendingPathComponent(".cache", isDirectory: true)
+    }
+
+    /// Created once the CLI has pointed at the missing Homebrew commands directory, so it only does so once.
+    public static var homebrewHintMarkerFile: URL {
+        cacheHome
+            .appendingPathComponent("lolabunny", isDirectory: true)
+            .appendingPathComponent("homebrew-hint-shown")
+    }
+
+    /// Values commands store with `kv_set`.
+    public static var keyValueStoreFile: URL {
+        cacheHome
+            .appendingPathComponent("lolabunny", isDirectory: true)
+            .appendingPathComponent("kv.json")
+    }
+
     public static var defaultVolumeDirectory: URL {
         let root = appDataHome
         let volume = root.appendingPathComponent("volume", isDirectory: true)
@@ -50,6 +78,32 @@ public enum Paths {
         return legacyVault
     }
 
+    public static var userCommandsDirectory: URL {
+        homeDirectory
+            .appendingPathComponent(appDirectoryName, isDirectory: true)
+            .appendingPathComponent("commands", isDirectory: true)
+    }
+
+    /// Shared Lua loaded into every command after the built-in helpers.
+    public static var preludeFile: URL {
+        homeDirectory
+            .appendingPathComponent(appDirectoryName, isDirectory: true)
+            .appendingPathComponent("prelude.lua")
+    }
+
+    /// System-wide directory admins deploy commands into, `/etc/lolabunny/commands` unless
+    /// `LOLABUNNY_SYSTEM_DIR` points elsewhere.
+    public static var systemCommandsDirectory: URL {
+        let root: URL
+        if let raw = ProcessInfo.processInfo.environment["LOLABUNNY_SYSTEM_DIR"],
+           !raw.trimmingCharacters(in: .whitespacesAndNewlines).isEmpty {
+            root = URL(fileURLWithPath: (raw as NSString).expandingTildeInPath, isDirectory: true)
+        } else {
+            root = URL(fileURLWithPath: "/etc/lolabunny", isDirectory: true)
+        }
+        return root.appendingPathComponent("commands", isDirectory: true)
+    }
+
     public static var executableDirectory: URL? {
         guard let executable = Bundle.main.executableURL else {
             return nil
@@ -71,6 +125,20 @@ public enum Paths {
     }
 
     public static func pluginDirectories() -> [URL] {
+        var seen = Set<String>()
+        return pluginDirectoryCandidates().flatMap { commandDirectoryCandidates(from: $0) }.compactMap { url in
+            let path = url.standardizedFileURL.path
+            guard seen.insert(path).inserted,
+                  isDirectory(at: url) else {
+                return nil
+            }
+            return URL(fileURLWithPath: path, isDirectory: true)
+        }
+    }
+
+    /// Every top-level directory scanned for commands, in precedence order, whether or not it exists.
+    /// Package directories inside them are scanned too.
+    public static func pluginDirectoryCandidates() -> [URL] {
         var candidates: [URL] = []
 
         if let executableDirectory {
@@ -104,29 +172,47 @@ public enum Paths {
         candidates.append(appDataHome.appendingPathComponent("commands", isDirectory: true))
         candidates.append(legacyAppDataHome.appendingPathComponent("commands", isDirectory: true))
 
-        for prefix in ["/opt/homebrew", "/usr/local", "/home/linuxbrew/.linuxbrew"] {
-            let root = URL(fileURLWithPath: prefix, isDirectory: true)
-            let brew = root.appendingPathComponent("bin/brew")
-            if FileManager.default.isExecutableFile(atPath: brew.path) {
-                for directoryName in homebrewShareDirectoryNames {
-                    candidates.append(
-                        root.appendingPathComponent("share", isDirectory: true)
-                            .appendingPathComponent(directoryName, isDirectory: true)
-                            .appendingPathComponent("commands", isDirectory: true)
-                    )
-                }
-            }
+        for root in homebrewPrefixes() {
+            candidates += homebrewShareDirectoryNames.map { homebrewCommandsDirectory(in: root, named: $0) }
         }
 
+        // Last, so user and brew commands win over system-wide ones with the same binding.
+        candidates.append(systemCommandsDirectory)
+
         var seen = Set<String>()
-        return candidates.flatMap { commandDirectoryCandidates(from: $0) }.compactMap { url in
-            let path = url.standardizedFileURL.path
-            guard seen.insert(path).inserted,
-                  isDirectory(at: url) else {
-                return nil
-            }
-            return URL(fileURLWithPath: path, isDirectory: true)
+        return candidates.filter { seen.insert($0.standardizedFileURL.path).inserted }
+    }
+
+    /// Where brew-installed commands go when Homebrew is present but no brew commands directory
+    /// exists yet, so `bindings` and `doctor` can point users at it. Nothing is created.
+    public static func missingHomebrewCommandsDirectory() -> URL? {
+        guard let root = homebrewPrefixes().first,
+              !homebrewShareDirectoryNames.contains(where: {
+                  isDirectory(at: homebrewCommandsDirectory(in: root, named: $0))
+              }) else {
+            return nil
         }
+        return homebrewCommandsDirectory(in: root, named: homebrewShareDirectoryNames[0])
+    }
+
+    /// Homebrew prefixes with a `bin/brew`; `LOLABUNNY_HOMEBREW_PREFIX` replaces the standard ones.
+    private static func homebrewPrefixes() -> [URL] {
+        let prefixes: [String]
+        if let raw = ProcessInfo.processInfo.environment["LOLABUNNY_HOMEBREW_PREFIX"],
+           !raw.trimmingCharacters(in: .whitespacesAndNewlines).isEmpty {
+            prefixes = [(raw as NSString).expandingTildeInPath]
+        } else {
+            prefixes = ["/opt/homebrew", "/usr/local", "/home/linuxbrew/.linuxbrew"]
+        }
+        return prefixes.map { URL(fileURLWithPath: $0, isDirectory: true) }.filter {
+            FileManager.default.isExecutableFile(atPath: $0.appendingPathComponent("bin/brew").path)
+        }
+    }
+
+    private static func homebrewCommandsDirectory(in root: URL, named directoryName: String) -> URL {
+        root.appendingPathComponent("share", isDirectory: true)
+            .appendingPathComponent(directoryName, isDirectory: true)
+            .appendingPathComponent("commands", isDirectory: true)
     }
 
     private static func commandDirectoryCandidates(from url: URL) -> [URL] {
@@ -139,7 +225,7 @@ public enum Paths {
             includingPropertiesForKeys: [.isDirectoryKey, .isSymbolicLinkKey],
             options: [.skipsHiddenFiles]
         )) ?? []
-        let childDirectories = children.filter { isDirectory(at: $0) }
+        let childDirectories = children.filter { isDirectory(at: $0) }.sorted { $0.path < $1.path }
 
         return [url] + childDirectories.map { $0.resolvingSymlinksInPath() }
     }